@@ -0,0 +1,55 @@
+//! Lint one or more diagram files
+//!
+//! Usage: lint_file [--disable <rule-id>]... <input.wsd>...
+//!
+//! Prints parse errors and lint warnings, and exits with status 1 if any
+//! were found so it can run in CI. Usage errors and unreadable files exit
+//! with status 2.
+
+use osd_core::lint::{lint_with_config, LintConfig, Rule};
+use osd_core::parse_with_diagnostics;
+use std::{env, fs, process};
+
+fn main() {
+    let mut config = LintConfig::default();
+    let mut files = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--disable" {
+            let id = args.next().unwrap_or_default();
+            match Rule::from_id(&id) {
+                Some(rule) => config = config.disable(rule),
+                None => {
+                    eprintln!("Unknown rule: {}", id);
+                    process::exit(2);
+                }
+            }
+        } else {
+            files.push(arg);
+        }
+    }
+    if files.is_empty() {
+        eprintln!("Usage: lint_file [--disable <rule-id>]... <input.wsd>...");
+        process::exit(2);
+    }
+
+    let mut found = 0;
+    for file in &files {
+        let input = match fs::read_to_string(file) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("error: {}: {}", file, e);
+                process::exit(2);
+            }
+        };
+        let output = parse_with_diagnostics(&input);
+        let warnings = lint_with_config(&output.diagram, &config);
+        for diagnostic in output.diagnostics.iter().chain(warnings.iter()) {
+            println!("{}: {}", file, diagnostic);
+            found += 1;
+        }
+    }
+    if found > 0 {
+        process::exit(1);
+    }
+}
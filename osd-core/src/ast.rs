@@ -1,5 +1,49 @@
 //! AST definitions for sequence diagrams
 
+/// Location of an item in the source text
+///
+/// Offsets are byte offsets into the input passed to the parser. Lines and
+/// columns are 1-based; columns count characters, not bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// Byte offset of the first character
+    pub start: usize,
+    /// Byte offset one past the last character
+    pub end: usize,
+    /// Line of the first character
+    pub line: usize,
+    /// Column of the first character
+    pub column: usize,
+    /// Line of the last character
+    pub end_line: usize,
+    /// Column one past the last character
+    pub end_column: usize,
+}
+
+impl Span {
+    /// Create a span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        let (first, last) = if self.start <= other.start {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        Span {
+            start: first.start,
+            end: last.end.max(first.end),
+            line: first.line,
+            column: first.column,
+            end_line: last.end_line,
+            end_column: last.end_column,
+        }
+    }
+
+    /// Check whether a byte offset falls inside this span
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
 /// Diagram options (parsed from option directives)
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct DiagramOptions {
@@ -28,6 +72,7 @@ impl Diagram {
             name: &str,
             alias: Option<&str>,
             kind: ParticipantKind,
+            span: Span,
             participants: &mut Vec<Participant>,
             seen: &mut std::collections::HashSet<String>,
         ) {
@@ -38,6 +83,8 @@ impl Diagram {
                     name: name.to_string(),
                     alias: alias.map(|s| s.to_string()),
                     kind,
+                    style: ParticipantStyle::default(),
+                    span,
                 });
             }
         }
@@ -48,9 +95,23 @@ impl Diagram {
             seen: &mut std::collections::HashSet<String>,
         ) {
             for item in items {
+                let span = item.span();
                 match item {
-                    Item::ParticipantDecl { name, alias, kind } => {
-                        add_participant(name, alias.as_deref(), *kind, participants, seen);
+                    Item::ParticipantDecl {
+                        name,
+                        alias,
+                        kind,
+                        style,
+                        ..
+                    } => {
+                        add_participant(name, alias.as_deref(), *kind, span, participants, seen);
+                        // Style comes from the declaration even if the participant was used earlier
+                        let id = alias.as_deref().unwrap_or(name);
+                        if let Some(p) = participants.iter_mut().find(|p| p.id() == id) {
+                            if p.style.is_empty() {
+                                p.style = style.clone();
+                            }
+                        }
                     }
                     Item::Message { from, to, .. } => {
                         // Skip boundary markers [ and ]
@@ -59,40 +120,44 @@ impl Diagram {
                                 from,
                                 None,
                                 ParticipantKind::Participant,
+                                span,
                                 participants,
                                 seen,
                             );
                         }
                         if to != "[" && to != "]" {
-                            add_participant(to, None, ParticipantKind::Participant, participants, seen);
+                            add_participant(to, None, ParticipantKind::Participant, span, participants, seen);
                         }
                     }
                     Item::Note { participants: note_participants, .. } => {
                         for p in note_participants {
-                            add_participant(p, None, ParticipantKind::Participant, participants, seen);
+                            add_participant(p, None, ParticipantKind::Participant, span, participants, seen);
                         }
                     }
                     Item::State { participants: state_participants, .. } => {
                         for p in state_participants {
-                            add_participant(p, None, ParticipantKind::Participant, participants, seen);
+                            add_participant(p, None, ParticipantKind::Participant, span, participants, seen);
                         }
                     }
                     Item::Ref { participants: ref_participants, input_from, output_to, .. } => {
                         // Add input_from first (e.g., Alice in "Alice->ref over Bob, Mary")
                         if let Some(from) = input_from {
-                            add_participant(from, None, ParticipantKind::Participant, participants, seen);
+                            add_participant(from, None, ParticipantKind::Participant, span, participants, seen);
                         }
                         // Then add ref participants (e.g., Bob, Mary)
                         for p in ref_participants {
-                            add_participant(p, None, ParticipantKind::Participant, participants, seen);
+                            add_participant(p, None, ParticipantKind::Participant, span, participants, seen);
                         }
                         // Finally add output_to if different
                         if let Some(to) = output_to {
-                            add_participant(to, None, ParticipantKind::Participant, participants, seen);
+                            add_participant(to, None, ParticipantKind::Participant, span, participants, seen);
                         }
                     }
-                    Item::Activate { participant } | Item::Deactivate { participant } | Item::Destroy { participant } => {
-                        add_participant(participant, None, ParticipantKind::Participant, participants, seen);
+                    Item::Activate { participant, .. }
+                    | Item::Deactivate { participant, .. }
+                    | Item::Destroy { participant, .. }
+                    | Item::Create { participant, .. } => {
+                        add_participant(participant, None, ParticipantKind::Participant, span, participants, seen);
                     }
                     Item::Block {
                         items, else_sections, ..
@@ -119,8 +184,12 @@ pub struct Participant {
     pub name: String,
     /// Optional short alias
     pub alias: Option<String>,
-    /// Kind of participant (actor or regular)
+    /// Kind of participant (box, actor, database, ...)
     pub kind: ParticipantKind,
+    /// Style overrides from the declaration
+    pub style: ParticipantStyle,
+    /// Source location of the declaration, or of the first item using it
+    pub span: Span,
 }
 
 impl Participant {
@@ -137,6 +206,81 @@ pub enum ParticipantKind {
     Participant,
     /// Actor (stick figure)
     Actor,
+    /// Database (cylinder)
+    Database,
+    /// Queue (horizontal cylinder)
+    Queue,
+    /// Boundary (ICONIX circle with a bar on the left)
+    Boundary,
+    /// Control (ICONIX circle with an arrowhead)
+    Control,
+    /// Entity (ICONIX circle with a base line)
+    Entity,
+    /// Collections (stacked boxes)
+    Collections,
+}
+
+impl ParticipantKind {
+    /// Keyword used to declare this kind
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParticipantKind::Participant => "participant",
+            ParticipantKind::Actor => "actor",
+            ParticipantKind::Database => "database",
+            ParticipantKind::Queue => "queue",
+            ParticipantKind::Boundary => "boundary",
+            ParticipantKind::Control => "control",
+            ParticipantKind::Entity => "entity",
+            ParticipantKind::Collections => "collections",
+        }
+    }
+}
+
+/// Inline style overrides on a participant declaration
+///
+/// Written as `participant Payment #ffe0e0` (fill only) or
+/// `participant Payment [fill=#ffe0e0, stroke=#c00, text=#900, lifeline=#c00]`.
+/// Colors are kept as written; unset fields fall back to the theme.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParticipantStyle {
+    /// Header/footer fill color
+    pub fill: Option<String>,
+    /// Header/footer outline color
+    pub stroke: Option<String>,
+    /// Name text color
+    pub text: Option<String>,
+    /// Lifeline color
+    pub lifeline: Option<String>,
+}
+
+impl ParticipantStyle {
+    pub fn is_empty(&self) -> bool {
+        self.fill.is_none() && self.stroke.is_none() && self.text.is_none() && self.lifeline.is_none()
+    }
+}
+
+/// Inline style overrides for a single message
+///
+/// Written inside the arrow (`A-[#red]>B: fail`) or as a trailing list
+/// (`A->B: fail [color=red, width=2, text=#900, bold]`). Unset fields fall
+/// back to the theme.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageStyle {
+    /// Line and arrowhead color
+    pub color: Option<String>,
+    /// Line stroke width
+    pub width: Option<f64>,
+    /// Label text color
+    pub text: Option<String>,
+}
+
+impl MessageStyle {
+    /// Stroke width used for `bold`
+    pub const BOLD_WIDTH: f64 = 3.0;
+
+    pub fn is_empty(&self) -> bool {
+        self.color.is_none() && self.width.is_none() && self.text.is_none()
+    }
 }
 
 /// A diagram item
@@ -147,6 +291,9 @@ pub enum Item {
         name: String,
         alias: Option<String>,
         kind: ParticipantKind,
+        /// Inline `#color` or `[fill=..., stroke=...]` overrides
+        style: ParticipantStyle,
+        span: Span,
     },
     /// Message between participants
     Message {
@@ -156,37 +303,66 @@ pub enum Item {
         arrow: Arrow,
         /// Activate the receiver
         activate: bool,
+        /// Fill of the receiver's activation bar (`->+[#color]`)
+        activation_color: Option<String>,
         /// Deactivate the sender
         deactivate: bool,
         /// Create the receiver
         create: bool,
+        /// Inline color and line overrides
+        style: MessageStyle,
+        /// Note drawn beside the arrow (`note on link: text` after the message)
+        note: Option<String>,
+        span: Span,
     },
     /// Note
     Note {
         position: NotePosition,
+        /// Outline drawn around the text (`note`, `rnote`, `hnote`)
+        shape: NoteShape,
+        /// Participants the note is attached to (empty for `note across`)
         participants: Vec<String>,
         text: String,
+        /// Background color (`note over A #yellow: text`)
+        color: Option<String>,
+        span: Span,
+    },
+    /// Activate a participant, optionally with a bar color (`activate A #color`)
+    Activate {
+        participant: String,
+        color: Option<String>,
+        span: Span,
     },
-    /// Activate a participant
-    Activate { participant: String },
     /// Deactivate a participant
-    Deactivate { participant: String },
+    Deactivate { participant: String, span: Span },
     /// Destroy a participant
-    Destroy { participant: String },
-    /// Block (alt, opt, loop, par)
+    Destroy { participant: String, span: Span },
+    /// Create a participant, drawing its header here instead of at the top
+    Create { participant: String, span: Span },
+    /// Block (alt, opt, loop, par, and the other UML combined fragments)
     Block {
         kind: BlockKind,
         label: String,
+        /// Message names listed by `ignore {...}` / `consider {...}`
+        messages: Vec<String>,
+        /// Secondary `[condition]` of a `group` block (its label goes in the tab)
+        condition: Option<String>,
         items: Vec<Item>,
         /// Multiple else sections (for alt blocks with multiple else branches)
         else_sections: Vec<ElseSection>,
+        /// From the opening keyword through the matching `end`
+        span: Span,
     },
     /// Autonumber control
-    Autonumber { enabled: bool, start: Option<u32> },
+    Autonumber {
+        command: AutonumberCommand,
+        span: Span,
+    },
     /// State box (rounded rectangle)
     State {
         participants: Vec<String>,
         text: String,
+        span: Span,
     },
     /// Reference box
     Ref {
@@ -200,11 +376,128 @@ pub enum Item {
         output_to: Option<String>,
         /// Output signal label
         output_label: Option<String>,
+        span: Span,
     },
     /// Diagram option
-    DiagramOption { key: String, value: String },
+    DiagramOption {
+        key: String,
+        value: String,
+        span: Span,
+    },
     /// Extended text description (indented comment)
-    Description { text: String },
+    Description { text: String, span: Span },
+    /// Box grouping participant lifelines (`box "Title" #color` ... `end box`)
+    ParticipantBox {
+        title: Option<String>,
+        /// Background color as written, e.g. `#lightblue` or `#e0f0ff`
+        color: Option<String>,
+        /// Ids of the participants declared inside the box
+        participants: Vec<String>,
+        /// From `box` through `end box`
+        span: Span,
+    },
+    /// Full-width section divider (`== Phase 2 ==`)
+    Divider { text: String, span: Span },
+    /// Vertical space: `|||` for one row or `||45||` for a height in pixels
+    Spacer { height: Option<u32>, span: Span },
+    /// Elided time (`...` or `... 5 min later ...`), drawn as dotted lifelines
+    Delay { text: String, span: Span },
+}
+
+impl Item {
+    /// Source location of this item
+    pub fn span(&self) -> Span {
+        match self {
+            Item::ParticipantDecl { span, .. }
+            | Item::Message { span, .. }
+            | Item::Note { span, .. }
+            | Item::Activate { span, .. }
+            | Item::Deactivate { span, .. }
+            | Item::Destroy { span, .. }
+            | Item::Create { span, .. }
+            | Item::Block { span, .. }
+            | Item::Autonumber { span, .. }
+            | Item::State { span, .. }
+            | Item::Ref { span, .. }
+            | Item::DiagramOption { span, .. }
+            | Item::Description { span, .. }
+            | Item::ParticipantBox { span, .. }
+            | Item::Divider { span, .. }
+            | Item::Spacer { span, .. }
+            | Item::Delay { span, .. } => *span,
+        }
+    }
+
+    /// Replace the source location of this item
+    pub fn set_span(&mut self, new_span: Span) {
+        match self {
+            Item::ParticipantDecl { span, .. }
+            | Item::Message { span, .. }
+            | Item::Note { span, .. }
+            | Item::Activate { span, .. }
+            | Item::Deactivate { span, .. }
+            | Item::Destroy { span, .. }
+            | Item::Create { span, .. }
+            | Item::Block { span, .. }
+            | Item::Autonumber { span, .. }
+            | Item::State { span, .. }
+            | Item::Ref { span, .. }
+            | Item::DiagramOption { span, .. }
+            | Item::Description { span, .. }
+            | Item::ParticipantBox { span, .. }
+            | Item::Divider { span, .. }
+            | Item::Spacer { span, .. }
+            | Item::Delay { span, .. } => *span = new_span,
+        }
+    }
+
+    /// Find the innermost item whose span contains the given byte offset
+    pub fn find_at(items: &[Item], offset: usize) -> Option<&Item> {
+        for item in items {
+            if !item.span().contains(offset) {
+                continue;
+            }
+            if let Item::Block {
+                items, else_sections, ..
+            } = item
+            {
+                if let Some(inner) = Item::find_at(items, offset) {
+                    return Some(inner);
+                }
+                for section in else_sections {
+                    if let Some(inner) = Item::find_at(&section.items, offset) {
+                        return Some(inner);
+                    }
+                }
+            }
+            return Some(item);
+        }
+        None
+    }
+}
+
+/// What an `autonumber` line does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AutonumberCommand {
+    /// `autonumber [start] [step] ["format"]`
+    ///
+    /// A dotted start (`1.1`) numbers hierarchically; `step` applies to the
+    /// last level. In the format, the first run of `0`s is replaced by the
+    /// zero-padded number (`"[000]"` gives `[001]`).
+    Start {
+        start: Vec<u32>,
+        step: u32,
+        format: Option<String>,
+    },
+    /// `autonumber off` or `autonumber stop`
+    Stop,
+    /// `autonumber resume [step] ["format"]`: continue where numbering stopped
+    Resume {
+        step: Option<u32>,
+        format: Option<String>,
+    },
+    /// `autonumber inc A`: increment a level (0 for `A`) and reset the levels below it
+    Increment { level: usize },
 }
 
 /// Arrow style
@@ -214,6 +507,8 @@ pub struct Arrow {
     pub line: LineStyle,
     /// Arrowhead style
     pub head: ArrowHead,
+    /// Arrowhead at the sender end (`<->`, `<-->`)
+    pub start_head: Option<ArrowHead>,
     /// Delay amount (for `->(n)` syntax)
     pub delay: Option<u32>,
 }
@@ -222,24 +517,28 @@ impl Arrow {
     pub const SYNC: Arrow = Arrow {
         line: LineStyle::Solid,
         head: ArrowHead::Filled,
+        start_head: None,
         delay: None,
     };
 
     pub const SYNC_OPEN: Arrow = Arrow {
         line: LineStyle::Solid,
         head: ArrowHead::Open,
+        start_head: None,
         delay: None,
     };
 
     pub const RESPONSE: Arrow = Arrow {
         line: LineStyle::Dashed,
         head: ArrowHead::Filled,
+        start_head: None,
         delay: None,
     };
 
     pub const RESPONSE_OPEN: Arrow = Arrow {
         line: LineStyle::Dashed,
         head: ArrowHead::Open,
+        start_head: None,
         delay: None,
     };
 }
@@ -260,6 +559,14 @@ pub enum ArrowHead {
     Filled,
     /// Open arrowhead (`->>`)
     Open,
+    /// Cross for lost or terminated messages (`->x`)
+    Cross,
+    /// Circle endpoint for found/lost messages (`o->`, `->o`)
+    Circle,
+    /// Upper half of a filled arrowhead (`-\`)
+    HalfUpper,
+    /// Lower half of a filled arrowhead (`-/`)
+    HalfLower,
 }
 
 /// Note position
@@ -271,6 +578,20 @@ pub enum NotePosition {
     Right,
     /// Over participant(s)
     Over,
+    /// Across all participants
+    Across,
+}
+
+/// Note outline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NoteShape {
+    /// Box with a folded corner (`note`)
+    #[default]
+    Folded,
+    /// Plain rectangle (`rnote`)
+    Rectangle,
+    /// Hexagon (`hnote`)
+    Hexagon,
 }
 
 /// Footer style for diagram (controlled by option footer=xxx)
@@ -302,6 +623,26 @@ pub enum BlockKind {
     Parallel,
     /// Serial with braces syntax
     Serial,
+    /// Break (the rest of the enclosing fragment is skipped)
+    Break,
+    /// Critical region (atomic)
+    Critical,
+    /// Negative (invalid traces)
+    Neg,
+    /// Ignore the listed messages
+    Ignore,
+    /// Consider only the listed messages
+    Consider,
+    /// Assertion (the only valid traces)
+    Assert,
+    /// Strict sequencing
+    Strict,
+    /// Region
+    Region,
+    /// Generic frame whose tab shows the block label
+    Group,
+    /// Messages drawn on one row (`A->B: x & A->C: y`)
+    Row,
 }
 
 impl BlockKind {
@@ -314,6 +655,16 @@ impl BlockKind {
             BlockKind::Seq => "seq",
             BlockKind::Parallel => "parallel",
             BlockKind::Serial => "serial",
+            BlockKind::Break => "break",
+            BlockKind::Critical => "critical",
+            BlockKind::Neg => "neg",
+            BlockKind::Ignore => "ignore",
+            BlockKind::Consider => "consider",
+            BlockKind::Assert => "assert",
+            BlockKind::Strict => "strict",
+            BlockKind::Region => "region",
+            BlockKind::Group => "group",
+            BlockKind::Row => "&",
         }
     }
 }
@@ -325,4 +676,6 @@ pub struct ElseSection {
     pub label: Option<String>,
     /// Items in this else section
     pub items: Vec<Item>,
+    /// From the `else` line through the last item of the section
+    pub span: Span,
 }
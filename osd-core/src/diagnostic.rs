@@ -0,0 +1,139 @@
+//! Diagnostics reported while parsing or checking a diagram
+
+use std::fmt;
+
+use crate::ast::Span;
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The input is invalid; the affected line was skipped
+    Error,
+    /// The input is accepted but probably not what was intended
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A suggested fix for a diagnostic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    /// Human-readable description of the fix
+    pub message: String,
+    /// Text to replace the diagnostic span with, when the fix is mechanical
+    pub replacement: Option<String>,
+}
+
+/// Another source location a diagnostic refers to, such as the start of an
+/// enclosing construct
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Related {
+    pub span: Span,
+    /// What is at that location (`outer box opened`)
+    pub message: String,
+}
+
+/// A problem found in the source, with its location
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Identifier of the rule that produced this diagnostic (lint rules only)
+    pub code: Option<&'static str>,
+    /// Source range the diagnostic applies to
+    pub span: Span,
+    /// Human-readable message
+    pub message: String,
+    /// Optional suggested fix
+    pub suggestion: Option<Suggestion>,
+    /// Optional second location the message refers to
+    pub related: Option<Related>,
+}
+
+impl Diagnostic {
+    /// Create an error diagnostic
+    pub fn error(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: None,
+            span,
+            message: message.into(),
+            suggestion: None,
+            related: None,
+        }
+    }
+
+    /// Create a warning diagnostic
+    pub fn warning(span: Span, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: None,
+            span,
+            message: message.into(),
+            suggestion: None,
+            related: None,
+        }
+    }
+
+    /// Attach a suggested fix
+    pub fn with_suggestion(mut self, message: impl Into<String>, replacement: Option<String>) -> Self {
+        self.suggestion = Some(Suggestion {
+            message: message.into(),
+            replacement,
+        });
+        self
+    }
+
+    /// Attach a second location the message refers to
+    pub fn with_related(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.related = Some(Related {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// The message followed by the related location, if any
+    pub fn full_message(&self) -> String {
+        match &self.related {
+            Some(related) => format!("{} ({} at line {})", self.message, related.message, related.span.line),
+            None => self.message.clone(),
+        }
+    }
+
+    /// Attach a rule identifier
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.severity.as_str())?;
+        if let Some(code) = self.code {
+            write!(f, "[{}]", code)?;
+        }
+        write!(
+            f,
+            ": line {}:{}: {}",
+            self.span.line,
+            self.span.column,
+            self.full_message()
+        )?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " ({})", suggestion.message)?;
+        }
+        Ok(())
+    }
+}
@@ -9,6 +9,8 @@
 #![allow(clippy::manual_strip)]
 #![allow(clippy::option_map_unit_fn)]
 #![allow(clippy::manual_inspect)]
+#![allow(clippy::collapsible_match)]
+#![allow(clippy::unnecessary_map_or)]
 //!
 //! # Example
 //!
@@ -38,7 +40,10 @@
 //! ```
 
 pub mod ast;
+pub mod diagnostic;
+pub mod lint;
 pub mod parser;
+pub mod preprocess;
 pub mod renderer;
 pub mod theme;
 
@@ -46,6 +51,13 @@ pub mod theme;
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub use ast::*;
-pub use parser::{parse, ParseError};
+pub use diagnostic::{Diagnostic, Related, Severity, Suggestion};
+pub use parser::{
+    parse, parse_strict, parse_with_diagnostics, parse_with_options, parse_with_resolver, ParseError, ParseOptions,
+    ParseOutput,
+};
+pub use preprocess::{IncludeResolver, IncludedSource, MemoryResolver};
+#[cfg(not(target_arch = "wasm32"))]
+pub use preprocess::FileResolver;
 pub use renderer::{render, render_with_config, Config};
 pub use theme::{LifelineStyle, ParticipantShape, Theme};
@@ -0,0 +1,405 @@
+//! Semantic checks over a parsed diagram
+//!
+//! The parser only validates syntax and structure. [`lint`] walks a
+//! [`Diagram`] in source order and reports issues such as deactivating a
+//! participant that is not active or messaging a destroyed participant.
+//! Every warning carries the [`Rule`] identifier in [`Diagnostic::code`],
+//! so rules can be turned off with [`LintConfig`].
+//!
+//! # Example
+//!
+//! ```
+//! use osd_core::lint::{lint_with_config, LintConfig, Rule};
+//! use osd_core::parse;
+//!
+//! let diagram = parse("participant Carol\nAlice->Bob: Hello\ndeactivate Bob").unwrap();
+//! let config = LintConfig::default().disable(Rule::UnusedParticipant);
+//! let warnings = lint_with_config(&diagram, &config);
+//! assert_eq!(warnings.len(), 1);
+//! assert_eq!(warnings[0].code, Some("deactivate-inactive"));
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+
+/// A lint rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// `deactivate` (or `-` on a message) for a participant that is not active
+    DeactivateInactive,
+    /// Activation that is never deactivated
+    UnclosedActivation,
+    /// Message sent to or from a participant after `destroy`
+    UseAfterDestroy,
+    /// Participant declared but never used
+    UnusedParticipant,
+    /// Participant that only appears in notes, states or refs
+    AnnotationOnlyParticipant,
+    /// `autonumber off` without an earlier `autonumber`
+    AutonumberOffWithoutOn,
+}
+
+impl Rule {
+    /// All rules, in the order they are documented
+    pub const ALL: &'static [Rule] = &[
+        Rule::DeactivateInactive,
+        Rule::UnclosedActivation,
+        Rule::UseAfterDestroy,
+        Rule::UnusedParticipant,
+        Rule::AnnotationOnlyParticipant,
+        Rule::AutonumberOffWithoutOn,
+    ];
+
+    /// Stable identifier used in diagnostics and configuration
+    pub fn id(&self) -> &'static str {
+        match self {
+            Rule::DeactivateInactive => "deactivate-inactive",
+            Rule::UnclosedActivation => "unclosed-activation",
+            Rule::UseAfterDestroy => "use-after-destroy",
+            Rule::UnusedParticipant => "unused-participant",
+            Rule::AnnotationOnlyParticipant => "annotation-only-participant",
+            Rule::AutonumberOffWithoutOn => "autonumber-off-without-on",
+        }
+    }
+
+    /// Look up a rule by its identifier
+    pub fn from_id(id: &str) -> Option<Rule> {
+        Rule::ALL.iter().copied().find(|rule| rule.id() == id)
+    }
+}
+
+/// Which lint rules are enabled (all by default)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintConfig {
+    disabled: HashSet<Rule>,
+}
+
+impl LintConfig {
+    /// Disable a rule
+    pub fn disable(mut self, rule: Rule) -> Self {
+        self.disabled.insert(rule);
+        self
+    }
+
+    /// Re-enable a previously disabled rule
+    pub fn enable(mut self, rule: Rule) -> Self {
+        self.disabled.remove(&rule);
+        self
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+}
+
+/// Lint a diagram with all rules enabled
+pub fn lint(diagram: &Diagram) -> Vec<Diagnostic> {
+    lint_with_config(diagram, &LintConfig::default())
+}
+
+/// Lint a diagram with the given rule configuration
+pub fn lint_with_config(diagram: &Diagram, config: &LintConfig) -> Vec<Diagnostic> {
+    let mut linter = Linter::default();
+    linter.walk(&diagram.items);
+    linter.finish(diagram);
+
+    let mut warnings: Vec<Diagnostic> = linter
+        .warnings
+        .into_iter()
+        .filter(|(rule, _)| config.is_enabled(*rule))
+        .map(|(rule, diagnostic)| diagnostic.with_code(rule.id()))
+        .collect();
+    warnings.sort_by_key(|d| d.span.start);
+    warnings
+}
+
+#[derive(Default)]
+struct Linter {
+    warnings: Vec<(Rule, Diagnostic)>,
+    /// Open activations per participant (span of the activating item)
+    activations: HashMap<String, Vec<Span>>,
+    /// Destroyed participants and where they were destroyed
+    destroyed: HashMap<String, Span>,
+    /// Participants used by messages and lifecycle statements
+    used: HashSet<String>,
+    /// Participants mentioned by notes, states and refs, with the first mention
+    annotated: Vec<(String, Span)>,
+    autonumber_seen: bool,
+}
+
+impl Linter {
+    fn warn(&mut self, rule: Rule, span: Span, message: String) {
+        self.warnings.push((rule, Diagnostic::warning(span, message)));
+    }
+
+    fn activate(&mut self, participant: &str, span: Span) {
+        self.activations.entry(participant.to_string()).or_default().push(span);
+    }
+
+    fn deactivate(&mut self, participant: &str, span: Span) {
+        let closed = self
+            .activations
+            .get_mut(participant)
+            .and_then(|open| open.pop())
+            .is_some();
+        if !closed {
+            self.warn(
+                Rule::DeactivateInactive,
+                span,
+                format!("`{}` is deactivated but is not active", participant),
+            );
+        }
+    }
+
+    fn check_destroyed(&mut self, participant: &str, span: Span) {
+        if let Some(destroyed_at) = self.destroyed.get(participant) {
+            let line = destroyed_at.line;
+            self.warn(
+                Rule::UseAfterDestroy,
+                span,
+                format!("`{}` is used after it was destroyed at line {}", participant, line),
+            );
+        }
+    }
+
+    fn annotate(&mut self, participants: impl IntoIterator<Item = String>, span: Span) {
+        for participant in participants {
+            if !self.annotated.iter().any(|(p, _)| *p == participant) {
+                self.annotated.push((participant, span));
+            }
+        }
+    }
+
+    fn walk(&mut self, items: &[Item]) {
+        for item in items {
+            match item {
+                Item::Message {
+                    from,
+                    to,
+                    activate,
+                    deactivate,
+                    create,
+                    span,
+                    ..
+                } => {
+                    if *create {
+                        self.destroyed.remove(to);
+                    }
+                    for participant in [from, to] {
+                        if participant != "[" && participant != "]" {
+                            self.check_destroyed(participant, *span);
+                            self.used.insert(participant.clone());
+                        }
+                    }
+                    if *activate {
+                        self.activate(to, *span);
+                    }
+                    if *deactivate {
+                        self.deactivate(from, *span);
+                    }
+                }
+                Item::Activate { participant, span, .. } => {
+                    self.check_destroyed(participant, *span);
+                    self.used.insert(participant.clone());
+                    self.activate(participant, *span);
+                }
+                Item::Deactivate { participant, span } => {
+                    self.used.insert(participant.clone());
+                    self.deactivate(participant, *span);
+                }
+                Item::Destroy { participant, span } => {
+                    self.used.insert(participant.clone());
+                    // Destroying a participant closes its activations
+                    self.activations.remove(participant);
+                    self.destroyed.insert(participant.clone(), *span);
+                }
+                Item::Create { participant, .. } => {
+                    self.used.insert(participant.clone());
+                    self.destroyed.remove(participant);
+                }
+                Item::Note {
+                    participants, span, ..
+                }
+                | Item::State {
+                    participants, span, ..
+                } => {
+                    self.annotate(participants.iter().cloned(), *span);
+                }
+                Item::Ref {
+                    participants,
+                    input_from,
+                    output_to,
+                    span,
+                    ..
+                } => {
+                    // Input/output signals are real interactions
+                    for participant in input_from.iter().chain(output_to.iter()) {
+                        self.used.insert(participant.clone());
+                    }
+                    self.annotate(participants.iter().cloned(), *span);
+                }
+                Item::Autonumber { command, span } => {
+                    if !matches!(command, AutonumberCommand::Stop) {
+                        self.autonumber_seen = true;
+                    } else if !self.autonumber_seen {
+                        self.warn(
+                            Rule::AutonumberOffWithoutOn,
+                            *span,
+                            "`autonumber off` without an earlier `autonumber`".to_string(),
+                        );
+                    }
+                }
+                Item::Block {
+                    items, else_sections, ..
+                } => {
+                    self.walk(items);
+                    for section in else_sections {
+                        self.walk(&section.items);
+                    }
+                }
+                Item::ParticipantDecl { .. }
+                | Item::DiagramOption { .. }
+                | Item::Description { .. }
+                | Item::ParticipantBox { .. }
+                | Item::Divider { .. }
+                | Item::Spacer { .. }
+                | Item::Delay { .. } => {}
+            }
+        }
+    }
+
+    fn finish(&mut self, diagram: &Diagram) {
+        let mut unclosed: Vec<(String, Span)> = self
+            .activations
+            .drain()
+            .flat_map(|(participant, spans)| spans.into_iter().map(move |span| (participant.clone(), span)))
+            .collect();
+        unclosed.sort_by_key(|(_, span)| span.start);
+        for (participant, span) in unclosed {
+            self.warn(
+                Rule::UnclosedActivation,
+                span,
+                format!("activation of `{}` is never deactivated", participant),
+            );
+        }
+
+        let declared: Vec<(String, Span)> = declarations(&diagram.items);
+        let annotated = std::mem::take(&mut self.annotated);
+        for (participant, span) in &declared {
+            let annotated_only = annotated.iter().any(|(p, _)| p == participant);
+            if !self.used.contains(participant) && !annotated_only {
+                self.warn(
+                    Rule::UnusedParticipant,
+                    *span,
+                    format!("participant `{}` is declared but never used", participant),
+                );
+            }
+        }
+        for (participant, span) in annotated {
+            // Declared or not, a participant with no messages is only an annotation
+            if !self.used.contains(&participant) {
+                self.warn(
+                    Rule::AnnotationOnlyParticipant,
+                    span,
+                    format!(
+                        "`{}` only appears in notes or refs and has no messages",
+                        participant
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Participant declarations (by id) with their spans
+fn declarations(items: &[Item]) -> Vec<(String, Span)> {
+    let mut declared = Vec::new();
+    for item in items {
+        match item {
+            Item::ParticipantDecl {
+                name, alias, span, ..
+            } => {
+                declared.push((alias.clone().unwrap_or_else(|| name.clone()), *span));
+            }
+            Item::Block {
+                items, else_sections, ..
+            } => {
+                declared.extend(declarations(items));
+                for section in else_sections {
+                    declared.extend(declarations(&section.items));
+                }
+            }
+            _ => {}
+        }
+    }
+    declared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn codes(input: &str) -> Vec<(usize, &'static str)> {
+        let diagram = parse(input).unwrap();
+        lint(&diagram)
+            .into_iter()
+            .map(|d| (d.span.line, d.code.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_diagram() {
+        let input = "participant Alice\nAlice->+Bob: call\nBob-->-Alice: result\nnote over Bob: done";
+        assert!(codes(input).is_empty());
+    }
+
+    #[test]
+    fn test_activation_rules() {
+        let input = "Alice->Bob: a\ndeactivate Bob\nactivate Alice\nAlice->+Bob: b";
+        assert_eq!(
+            codes(input),
+            vec![
+                (2, "deactivate-inactive"),
+                (3, "unclosed-activation"),
+                (4, "unclosed-activation"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_use_after_destroy() {
+        let input = "Alice->Bob: a\ndestroy Bob\nAlice->Bob: b\nAlice->*Bob: again\nAlice->Bob: ok";
+        assert_eq!(codes(input), vec![(3, "use-after-destroy")]);
+    }
+
+    #[test]
+    fn test_participant_usage_rules() {
+        let input = "participant Unused\nAlice->Bob: a\nnote over Carol: only here\nref over Bob, Dave: see\nautonumber off";
+        assert_eq!(
+            codes(input),
+            vec![
+                (1, "unused-participant"),
+                (3, "annotation-only-participant"),
+                (4, "annotation-only-participant"),
+                (5, "autonumber-off-without-on"),
+            ]
+        );
+
+        // Declaring the participant doesn't make a note over it a use
+        let input = "participant Carol\nAlice->Bob: a\nnote over Carol: x";
+        assert_eq!(codes(input), vec![(3, "annotation-only-participant")]);
+    }
+
+    #[test]
+    fn test_disabled_rules() {
+        let diagram = parse("participant Unused\nautonumber off").unwrap();
+        let config = LintConfig::default()
+            .disable(Rule::from_id("unused-participant").unwrap())
+            .disable(Rule::AutonumberOffWithoutOn);
+        assert!(lint_with_config(&diagram, &config).is_empty());
+        assert_eq!(lint_with_config(&diagram, &config.enable(Rule::UnusedParticipant)).len(), 1);
+    }
+}
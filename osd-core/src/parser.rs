@@ -3,14 +3,18 @@
 use nom::{
     branch::alt,
     bytes::complete::{tag, tag_no_case, take_until, take_while, take_while1},
-    character::complete::{char, digit1, space0, space1},
-    combinator::{map, opt, value},
+    character::complete::{char, space0, space1},
+    combinator::{eof, opt, peek, recognize, value},
     multi::separated_list1,
-    sequence::{delimited, pair, preceded},
+    sequence::{delimited, pair, preceded, terminated},
     IResult, Parser,
 };
 
+use std::collections::HashMap;
+
 use crate::ast::*;
+use crate::diagnostic::Diagnostic;
+use crate::preprocess::{self, IncludeResolver};
 
 /// Parse error
 #[derive(Debug, Clone, thiserror::Error)]
@@ -19,65 +23,333 @@ pub enum ParseError {
     SyntaxError { line: usize, message: String },
 }
 
-/// Parse a complete diagram
+impl From<Diagnostic> for ParseError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        ParseError::SyntaxError {
+            line: diagnostic.span.line,
+            message: diagnostic.full_message(),
+        }
+    }
+}
+
+/// Result of a recovering parse
+#[derive(Debug, Clone)]
+pub struct ParseOutput {
+    /// Best-effort diagram (lines with errors are skipped)
+    pub diagram: Diagram,
+    /// All problems found, in source order
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ParseOutput {
+    /// Check whether any error-level diagnostic was reported
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.is_error())
+    }
+
+    /// Convert into the diagram, or the first error-level diagnostic
+    pub fn into_result(self) -> Result<Diagram, ParseError> {
+        match self.diagnostics.into_iter().find(|d| d.is_error()) {
+            Some(error) => Err(error.into()),
+            None => Ok(self.diagram),
+        }
+    }
+}
+
+/// A line of source text with its position in the input
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SourceLine<'a> {
+    /// Line content without the line terminator
+    pub(crate) text: &'a str,
+    /// Byte offset of the line start in the input
+    pub(crate) offset: usize,
+    /// 1-based line number
+    pub(crate) number: usize,
+}
+
+impl<'a> SourceLine<'a> {
+    /// Split input into lines, tracking offsets (handles `\n` and `\r\n`)
+    pub(crate) fn split(input: &'a str) -> Vec<SourceLine<'a>> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        for (i, raw) in input.split_inclusive('\n').enumerate() {
+            let text = raw.strip_suffix('\n').unwrap_or(raw);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            lines.push(SourceLine {
+                text,
+                offset,
+                number: i + 1,
+            });
+            offset += raw.len();
+        }
+        lines
+    }
+
+    /// Span of the trimmed content of this line
+    pub(crate) fn span(&self) -> Span {
+        let leading = self.text.len() - self.text.trim_start().len();
+        let trimmed = self.text.trim();
+        let column = self.text[..leading].chars().count() + 1;
+        Span {
+            start: self.offset + leading,
+            end: self.offset + leading + trimmed.len(),
+            line: self.number,
+            column,
+            end_line: self.number,
+            end_column: column + trimmed.chars().count(),
+        }
+    }
+}
+
+/// Parser options
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Report structural problems (unterminated blocks, orphan `else`/`end`,
+    /// unclosed notes and refs) as errors instead of warnings
+    pub strict: bool,
+}
+
+impl ParseOptions {
+    /// Options with strict structural validation enabled
+    pub fn strict() -> Self {
+        Self { strict: true }
+    }
+}
+
+/// Parse a complete diagram, failing on the first error
 pub fn parse(input: &str) -> Result<Diagram, ParseError> {
-    let mut items = Vec::new();
+    parse_with_options(input, ParseOptions::default()).into_result()
+}
+
+/// Parse a complete diagram with strict structural validation
+///
+/// Unlike [`parse`], an unterminated block, an orphan `else`/`end` or an
+/// unclosed multiline note/ref is an error.
+pub fn parse_strict(input: &str) -> Result<Diagram, ParseError> {
+    parse_with_options(input, ParseOptions::strict()).into_result()
+}
+
+/// Parse a complete diagram, recovering from errors
+///
+/// Lines that cannot be parsed are skipped and reported as diagnostics, so
+/// every problem in the input is returned alongside a best-effort diagram.
+pub fn parse_with_diagnostics(input: &str) -> ParseOutput {
+    parse_with_options(input, ParseOptions::default())
+}
+
+/// Parse a complete diagram with the given options, recovering from errors
+pub fn parse_with_options(input: &str, options: ParseOptions) -> ParseOutput {
+    parse_source(input, None, options)
+}
+
+/// Parse a complete diagram, resolving `!include` directives with `resolver`
+///
+/// Problems inside an included file are reported at the `!include` line of
+/// `input`, with the include chain in the message.
+pub fn parse_with_resolver(input: &str, resolver: &dyn IncludeResolver) -> Result<Diagram, ParseError> {
+    parse_source(input, Some(resolver), ParseOptions::default()).into_result()
+}
+
+fn parse_source(input: &str, resolver: Option<&dyn IncludeResolver>, options: ParseOptions) -> ParseOutput {
+    let expansion = preprocess::expand(input, resolver);
+    let mut diagnostics = Vec::new();
+    let mut diagram = parse_document(&expansion.text, options, &mut diagnostics);
+    expansion.remap(&mut diagram.items, &mut diagnostics);
+    diagnostics.extend(expansion.diagnostics);
+    diagnostics.sort_by_key(|d| d.span.start);
+    ParseOutput {
+        diagram,
+        diagnostics,
+    }
+}
+
+/// Create a structural diagnostic whose severity depends on strict mode
+fn structural(options: ParseOptions, span: Span, message: String) -> Diagnostic {
+    if options.strict {
+        Diagnostic::error(span, message)
+    } else {
+        Diagnostic::warning(span, message)
+    }
+}
+
+fn parse_document(input: &str, options: ParseOptions, diagnostics: &mut Vec<Diagnostic>) -> Diagram {
     let mut title = None;
-    let lines: Vec<&str> = input.lines().collect();
+    let lines = SourceLine::split(input);
     let mut i = 0;
+    let mut calls = CallStack::default();
+    let items = parse_statements(&lines, &mut i, false, &mut title, &mut calls, options, diagnostics);
 
-    while i < lines.len() {
-        let line = lines[i];
+    // Extract options from items
+    let mut options = DiagramOptions::default();
+    for item in &items {
+        if let Item::DiagramOption { key, value, .. } = item {
+            if key.eq_ignore_ascii_case("footer") {
+                options.footer = match value.to_lowercase().as_str() {
+                    "none" => FooterStyle::None,
+                    "bar" => FooterStyle::Bar,
+                    "box" => FooterStyle::Box,
+                    _ => FooterStyle::Box,
+                };
+            }
+        }
+    }
+
+    Diagram {
+        title,
+        items,
+        options,
+    }
+}
+
+/// Parse statements starting at `lines[*i]` and build their block structure
+///
+/// Inside a `parallel {` / `serial {` body (`in_brace`) parsing stops at the
+/// matching `}` and leaves `*i` on it; otherwise it runs to the end of input.
+/// Brace bodies recurse, so every statement is valid at any depth.
+fn parse_statements(
+    lines: &[SourceLine],
+    i: &mut usize,
+    in_brace: bool,
+    title: &mut Option<String>,
+    calls: &mut CallStack,
+    options: ParseOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Vec<Item> {
+    let mut items = Vec::new();
+    // Participant box opened by `box` and not yet closed by `end box`
+    let mut open_box: Option<OpenBox> = None;
+
+    while *i < lines.len() {
+        let line = lines[*i].text;
         let trimmed = line.trim();
+        let span = lines[*i].span();
 
         // Skip empty lines
         if trimmed.is_empty() {
-            i += 1;
+            *i += 1;
             continue;
         }
 
         // Task 5: Skip comment lines (# ...)
         if trimmed.starts_with('#') {
-            i += 1;
+            *i += 1;
             continue;
         }
 
+        // Closing brace of the enclosing parallel/serial body
+        if in_brace && trimmed == "}" {
+            break;
+        }
+
         // Task 7: Extended text description (lines starting with space but not empty)
-        if line.starts_with(' ') && !trimmed.is_empty() && !line.starts_with("  ") {
+        if !in_brace && line.starts_with(' ') && !trimmed.is_empty() && !line.starts_with("  ") {
             // Single space indent is description
             items.push(Item::Description {
                 text: trimmed.to_string(),
+                span,
             });
-            i += 1;
+            *i += 1;
             continue;
         }
 
         // Try parsing title first
         if let Ok((_, t)) = parse_title(trimmed) {
-            title = Some(t);
-            i += 1;
+            *title = Some(t);
+            *i += 1;
+            continue;
+        }
+
+        // Participant grouping box: `box "Title" #color` ... `end box`
+        if let Some((title, color)) = parse_box_start(trimmed) {
+            if let Some(outer) = &open_box {
+                diagnostics.push(
+                    Diagnostic::error(span, "`box` cannot be nested")
+                        .with_related(outer.span, "outer box opened")
+                        .with_suggestion("close the previous box with `end box` first", None),
+                );
+            } else {
+                open_box = Some(OpenBox {
+                    title,
+                    color,
+                    participants: Vec::new(),
+                    span,
+                });
+            }
+            *i += 1;
+            continue;
+        }
+        if trimmed.eq_ignore_ascii_case("end box") {
+            match open_box.take() {
+                Some(open) => items.push(open.close(span)),
+                None => diagnostics.push(
+                    structural(options, span, "`end box` without a matching `box`".to_string())
+                        .with_suggestion("remove this `end box`", Some(String::new())),
+                ),
+            }
+            *i += 1;
+            continue;
+        }
+
+        // `note on link: text` annotates the message just before it
+        if let Some(text) = parse_link_note(trimmed) {
+            let last = match items.last_mut() {
+                // A row's note belongs to its last message
+                Some(Item::Block {
+                    kind: BlockKind::Row,
+                    items: row,
+                    ..
+                }) => row.last_mut(),
+                last => last,
+            };
+            match last {
+                Some(Item::Message { note: Some(note), .. }) => {
+                    note.push_str("\\n");
+                    note.push_str(&text);
+                }
+                Some(Item::Message { note, .. }) => *note = Some(text),
+                _ => diagnostics.push(
+                    Diagnostic::error(span, "`note on link` must follow a message")
+                        .with_suggestion("move it below the message it describes, or use `note over`", None),
+                ),
+            }
+            *i += 1;
             continue;
         }
 
         // Task 1: Check for multiline note (note without colon)
-        if let Some((position, participants)) = parse_multiline_note_start(trimmed) {
+        if let Some(note) = parse_multiline_note_start(trimmed) {
             let mut note_lines = Vec::new();
-            i += 1;
-            while i < lines.len() {
-                let note_line = lines[i].trim();
-                if note_line.eq_ignore_ascii_case("end note") {
+            let mut note_span = span;
+            *i += 1;
+            while *i < lines.len() {
+                let note_line = lines[*i].text.trim();
+                note_span = note_span.to(lines[*i].span());
+                if is_note_end(note_line) {
                     break;
                 }
                 note_lines.push(note_line);
-                i += 1;
+                *i += 1;
+            }
+            if *i >= lines.len() {
+                diagnostics.push(
+                    structural(
+                        options,
+                        span,
+                        "note is never closed with `end note`".to_string(),
+                    )
+                    .with_suggestion("add `end note` after the note text", None),
+                );
             }
             let text = note_lines.join("\\n");
             items.push(Item::Note {
-                position,
-                participants,
+                position: note.position,
+                shape: note.shape,
+                participants: note.participants,
                 text,
+                color: note.color,
+                span: note_span,
             });
-            i += 1;
+            *i += 1;
             continue;
         }
 
@@ -87,9 +359,11 @@ pub fn parse(input: &str) -> Result<Diagram, ParseError> {
             let mut ref_lines = Vec::new();
             let mut output_to: Option<String> = None;
             let mut output_label: Option<String> = None;
-            i += 1;
-            while i < lines.len() {
-                let ref_line = lines[i].trim();
+            let mut ref_span = span;
+            *i += 1;
+            while *i < lines.len() {
+                let ref_line = lines[*i].text.trim();
+                ref_span = ref_span.to(lines[*i].span());
                 // Check for end ref with optional output signal
                 if let Some((out_to, out_label)) = parse_ref_end(ref_line) {
                     output_to = out_to;
@@ -97,7 +371,17 @@ pub fn parse(input: &str) -> Result<Diagram, ParseError> {
                     break;
                 }
                 ref_lines.push(ref_line);
-                i += 1;
+                *i += 1;
+            }
+            if *i >= lines.len() {
+                diagnostics.push(
+                    structural(
+                        options,
+                        span,
+                        "ref is never closed with `end ref`".to_string(),
+                    )
+                    .with_suggestion("add `end ref` after the ref text", None),
+                );
             }
             let text = ref_lines.join("\\n");
             items.push(Item::Ref {
@@ -107,138 +391,412 @@ pub fn parse(input: &str) -> Result<Diagram, ParseError> {
                 input_label: ref_start.input_label,
                 output_to,
                 output_label,
+                span: ref_span,
             });
-            i += 1;
+            *i += 1;
             continue;
         }
 
         // Task 8: Check for parallel { or serial { brace syntax
         if let Some((kind, remaining)) = parse_brace_block_start(trimmed) {
-            let mut block_items = Vec::new();
-            let mut brace_depth = 1;
-
-            // Check if there's content after the opening brace on the same line
-            let after_brace = remaining.trim();
-            if !after_brace.is_empty() && after_brace != "{" {
-                // Parse content after brace if any
+            if !remaining.trim().is_empty() {
+                let offset = trimmed.len() - remaining.len();
+                diagnostics.push(
+                    Diagnostic::error(
+                        subspan(span, trimmed, offset, trimmed.len()),
+                        format!("unexpected text after `{} {{`", kind.as_str()),
+                    )
+                    .with_suggestion("move the statement to its own line", None),
+                );
             }
+            *i += 1;
+            calls.enter(kind);
+            let block_items = parse_statements(lines, i, true, title, calls, options, diagnostics);
+            calls.leave();
+            let block_span = if *i < lines.len() {
+                span.to(lines[*i].span())
+            } else {
+                diagnostics.push(
+                    structural(
+                        options,
+                        span,
+                        format!("`{} {{` is never closed with `}}`", kind.as_str()),
+                    )
+                    .with_suggestion("add a closing `}`", None),
+                );
+                block_items.last().map_or(span, |last| span.to(last.span()))
+            };
 
-            i += 1;
-            while i < lines.len() && brace_depth > 0 {
-                let block_line = lines[i].trim();
-
-                if block_line == "}" {
-                    brace_depth -= 1;
-                    if brace_depth == 0 {
-                        break;
-                    }
-                    i += 1;
-                    continue;
-                }
+            items.push(Item::Block {
+                kind,
+                label: String::new(),
+                messages: vec![],
+                condition: None,
+                items: block_items,
+                else_sections: vec![],
+                span: block_span,
+            });
+            *i += 1;
+            continue;
+        }
 
-                if !block_line.is_empty() && !block_line.starts_with('#') {
-                    // Recursively parse nested content
-                    if let Some((nested_kind, _)) = parse_brace_block_start(block_line) {
-                        // Handle nested parallel/serial blocks
-                        let mut nested_items = Vec::new();
-                        let mut nested_depth = 1;
-                        i += 1;
-
-                        while i < lines.len() && nested_depth > 0 {
-                            let nested_line = lines[i].trim();
-                            if nested_line == "}" {
-                                nested_depth -= 1;
-                                if nested_depth == 0 {
-                                    break;
-                                }
-                            } else if nested_line.ends_with('{') {
-                                nested_depth += 1;
-                            }
-
-                            if nested_depth > 0
-                                && !nested_line.is_empty()
-                                && !nested_line.starts_with('#')
-                            {
-                                if let Ok((_, item)) = parse_line(nested_line) {
-                                    nested_items.push(item);
-                                }
-                            }
-                            i += 1;
-                        }
+        // `return label`: dashed response from the latest activated participant to its caller
+        if let Some(text) = parse_return(trimmed) {
+            match calls.pop() {
+                Some((callee, Some(caller))) => items.push(Item::Message {
+                    from: callee,
+                    to: caller,
+                    text,
+                    arrow: Arrow::RESPONSE,
+                    activate: false,
+                    activation_color: None,
+                    deactivate: true,
+                    create: false,
+                    style: MessageStyle::default(),
+                    note: None,
+                    span,
+                }),
+                Some((callee, None)) => diagnostics.push(
+                    Diagnostic::error(span, format!("`return` cannot tell who called `{}`", callee))
+                        .with_suggestion(format!("write the response as a message, e.g. `{}-->Caller: {}`", callee, text), None),
+                ),
+                None => diagnostics.push(
+                    Diagnostic::error(span, "`return` without an active call")
+                        .with_suggestion("activate the callee first, e.g. `Alice->+Bob: request`", None),
+                ),
+            }
+            *i += 1;
+            continue;
+        }
 
-                        block_items.push(Item::Block {
-                            kind: nested_kind,
-                            label: String::new(),
-                            items: nested_items,
-                            else_sections: vec![],
-                        });
-                    } else if let Ok((_, item)) = parse_line(block_line) {
-                        block_items.push(item);
-                    }
+        // `& A->C: y` adds a message to the row of the message above it
+        if let Some(rest) = trimmed.strip_prefix('&') {
+            let rest = rest.trim_start();
+            let rest_span = subspan(span, trimmed, trimmed.len() - rest.len(), trimmed.len());
+            match parse_row_messages(rest, rest_span) {
+                None => diagnostics.push(diagnose_line(rest, rest_span)),
+                Some(messages) if items.last_mut().map_or(false, |last| join_row(last, &messages, span)) => {
+                    messages.iter().for_each(|message| calls.track(message));
                 }
-                i += 1;
+                Some(_) => diagnostics.push(
+                    Diagnostic::error(subspan(span, trimmed, 0, 1), "`&` must follow a message")
+                        .with_suggestion("remove the `&` to send this message on its own row", None),
+                ),
             }
+            *i += 1;
+            continue;
+        }
 
+        // `A->B: x & A->C: y` sends several messages on one row
+        let row = if trimmed.contains('&') {
+            parse_row_messages(trimmed, span)
+        } else {
+            None
+        };
+        if let Some(messages) = row.filter(|messages| messages.len() > 1) {
+            messages.iter().for_each(|message| calls.track(message));
             items.push(Item::Block {
-                kind,
+                kind: BlockKind::Row,
                 label: String::new(),
-                items: block_items,
-                else_sections: vec![],
+                messages: Vec::new(),
+                condition: None,
+                items: messages,
+                else_sections: Vec::new(),
+                span,
             });
-            i += 1;
+            *i += 1;
             continue;
         }
 
         // Regular line parsing
         match parse_line(trimmed) {
-            Ok((_, item)) => {
+            Ok((_, mut item)) => {
+                item.set_span(span);
+                calls.track(&item);
+                if let (Some(open), Item::ParticipantDecl { name, alias, .. }) = (&mut open_box, &item) {
+                    open.participants.push(alias.clone().unwrap_or_else(|| name.clone()));
+                }
                 items.push(item);
             }
-            Err(e) => {
-                return Err(ParseError::SyntaxError {
-                    line: i + 1,
-                    message: format!("Failed to parse: {:?}", e),
-                });
+            Err(_) => {
+                diagnostics.push(diagnose_line(trimmed, span));
             }
         }
-        i += 1;
+        *i += 1;
+    }
+
+    if let Some(open) = open_box {
+        diagnostics.push(
+            structural(
+                options,
+                open.span,
+                "box is never closed with `end box`".to_string(),
+            )
+            .with_suggestion("add `end box` after the last participant", None),
+        );
+        let end = items.last().map_or(open.span, |last| last.span());
+        items.push(open.close(end));
     }
 
     // Second pass: handle blocks (alt/opt/loop/par/end/else)
-    let items = build_blocks(items)?;
+    build_blocks(items, options, diagnostics)
+}
 
-    // Extract options from items
-    let mut options = DiagramOptions::default();
-    for item in &items {
-        if let Item::DiagramOption { key, value } = item {
-            if key.eq_ignore_ascii_case("footer") {
-                options.footer = match value.to_lowercase().as_str() {
-                    "none" => FooterStyle::None,
-                    "bar" => FooterStyle::Bar,
-                    "box" => FooterStyle::Box,
-                    _ => FooterStyle::Box,
-                };
+/// Open calls in source order, used to resolve `return`
+#[derive(Default)]
+struct CallStack {
+    /// Activated participant and its caller (unknown for a bare `activate`), latest last
+    calls: Vec<(String, Option<String>)>,
+    /// Sender of the latest message to each participant
+    last_sender: HashMap<String, String>,
+    /// Enclosing blocks and the calls open when each was entered, innermost last
+    blocks: Vec<(BlockKind, Vec<(String, Option<String>)>)>,
+}
+
+impl CallStack {
+    fn track(&mut self, item: &Item) {
+        match item {
+            Item::Message {
+                from,
+                to,
+                activate,
+                deactivate,
+                ..
+            } => {
+                if *deactivate {
+                    self.close(from);
+                }
+                self.last_sender.insert(to.clone(), from.clone());
+                if *activate {
+                    self.calls.push((to.clone(), Some(from.clone())));
+                }
             }
+            Item::Activate { participant, .. } => {
+                let caller = self.last_sender.get(participant).cloned();
+                self.calls.push((participant.clone(), caller));
+            }
+            Item::Deactivate { participant, .. } => self.close(participant),
+            Item::Destroy { participant, .. } => self.calls.retain(|(callee, _)| callee != participant),
+            Item::Block { label, .. } if label == "__END__" => self.leave(),
+            Item::Block { label, .. } if label.starts_with("__ELSE__") => self.next_branch(),
+            Item::Block { kind, label, items, .. } if !label.starts_with("__") && items.is_empty() => self.enter(*kind),
+            _ => {}
         }
     }
 
-    Ok(Diagram {
-        title,
-        items,
-        options,
-    })
+    /// Enter a block; each of its branches starts from the calls open here
+    fn enter(&mut self, kind: BlockKind) {
+        self.blocks.push((kind, self.calls.clone()));
+    }
+
+    /// Start the next `else` branch of the innermost block
+    fn next_branch(&mut self) {
+        if let Some((_, open)) = self.blocks.last() {
+            self.calls = open.clone();
+        }
+    }
+
+    /// Leave the innermost block. Sequential branches hand on the calls still open,
+    /// but calls left open by concurrent branches don't outlive them.
+    fn leave(&mut self) {
+        if let Some((kind, open)) = self.blocks.pop() {
+            if matches!(kind, BlockKind::Par | BlockKind::Parallel) {
+                self.calls = open;
+            }
+        }
+    }
+
+    /// Close the latest activation of `participant`
+    fn close(&mut self, participant: &str) {
+        if let Some(index) = self.calls.iter().rposition(|(callee, _)| callee == participant) {
+            self.calls.remove(index);
+        }
+    }
+
+    fn pop(&mut self) -> Option<(String, Option<String>)> {
+        self.calls.pop()
+    }
 }
 
-/// Check if line starts a multiline note (note without colon)
-fn parse_multiline_note_start(input: &str) -> Option<(NotePosition, Vec<String>)> {
-    let input_lower = input.to_lowercase();
+/// Label of a `return` statement (`return` or `return text`)
+fn parse_return(input: &str) -> Option<String> {
+    let keyword = input.get(..6)?;
+    if !keyword.eq_ignore_ascii_case("return") {
+        return None;
+    }
+    let rest = &input[6..];
+    let label = rest.trim();
+    let is_keyword = rest.is_empty() || rest.starts_with(char::is_whitespace);
+    // `return -> Bob` and `Return Handler->Bob: hi` are messages from participants
+    // named like the keyword
+    (is_keyword && !label.starts_with(['-', '<']) && !is_message_line(input)).then(|| label.to_string())
+}
+
+/// Messages separated by ` & ` (`A->B: x & A->C: y`), each with its own span. A `&`
+/// only separates messages when spaces surround it and a message follows it, so `&`
+/// in label text (`R&D->QA handoff`, `Q & A`) is kept.
+fn parse_row_messages(line: &str, span: Span) -> Option<Vec<Item>> {
+    let is_message = |text: &str| matches!(parse_message(text.trim()), Ok((_, Item::Message { .. })));
+    let mut starts = vec![0];
+    for (pos, _) in line.match_indices(" & ") {
+        if is_message(&line[pos + 2..]) {
+            starts.push(pos + 2);
+        }
+    }
+    let mut messages = Vec::new();
+    for (index, &start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).map_or(line.len(), |next| next - 2);
+        let part = &line[start..end];
+        let part_start = start + (part.len() - part.trim_start().len());
+        let part_end = start + part.trim_end().len();
+        let (_, mut message) = parse_message(line[part_start..part_end].trim()).ok()?;
+        if !matches!(message, Item::Message { .. }) {
+            return None;
+        }
+        message.set_span(subspan(span, line, part_start, part_end));
+        messages.push(message);
+    }
+    Some(messages)
+}
+
+/// Add messages to the row of `last`, turning a lone message into a row
+fn join_row(last: &mut Item, messages: &[Item], span: Span) -> bool {
+    match last {
+        Item::Block {
+            kind: BlockKind::Row,
+            items,
+            span: row_span,
+            ..
+        } => {
+            items.extend_from_slice(messages);
+            *row_span = row_span.to(span);
+            true
+        }
+        Item::Message { .. } => {
+            let row_span = last.span().to(span);
+            let mut items = vec![last.clone()];
+            items.extend_from_slice(messages);
+            *last = Item::Block {
+                kind: BlockKind::Row,
+                label: String::new(),
+                messages: Vec::new(),
+                condition: None,
+                items,
+                else_sections: Vec::new(),
+                span: row_span,
+            };
+            true
+        }
+        _ => false,
+    }
+}
 
-    // Must start with "note" but not have a colon
-    if !input_lower.starts_with("note ") || input.contains(':') {
+/// `note on link: text`, returning the text
+fn parse_link_note(input: &str) -> Option<String> {
+    let (shape, rest) = split_note_keyword(input)?;
+    let lower = rest.to_ascii_lowercase();
+    let after_on = lower.strip_prefix("on").filter(|r| r.starts_with(char::is_whitespace))?;
+    let after_link = after_on.trim_start().strip_prefix("link")?.trim_start();
+    let is_link_note = shape == NoteShape::Folded && after_link.starts_with(':');
+    is_link_note.then(|| rest[rest.len() - after_link.len() + 1..].trim().to_string())
+}
+
+/// A `box` whose participants are still being collected
+struct OpenBox {
+    title: Option<String>,
+    color: Option<String>,
+    participants: Vec<String>,
+    span: Span,
+}
+
+impl OpenBox {
+    fn close(self, end: Span) -> Item {
+        Item::ParticipantBox {
+            title: self.title,
+            color: self.color,
+            participants: self.participants,
+            span: self.span.to(end),
+        }
+    }
+}
+
+/// Check if line starts a participant box: `box`, `box "Title"`, `box Title #color`
+fn parse_box_start(input: &str) -> Option<(Option<String>, Option<String>)> {
+    let keyword = input.get(..3)?;
+    if !keyword.eq_ignore_ascii_case("box") {
+        return None;
+    }
+    let rest = &input[3..];
+    if !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    let rest = rest.trim();
+    // `Box -> Bob: hi` and `Box Office->Alice: ticket` are messages, not boxes
+    if rest.starts_with('-') || is_message_line(input) {
         return None;
     }
 
-    let rest = &input[5..].trim();
+    // Trailing `#color`, unless it is part of a quoted title
+    let (title, color) = match rest.rfind('#') {
+        Some(pos) if !rest[pos..].contains(char::is_whitespace) && !rest[pos..].contains('"') => {
+            (rest[..pos].trim(), Some(rest[pos..].to_string()))
+        }
+        _ => (rest, None),
+    };
+    let title = title
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(title);
+    let title = if title.is_empty() {
+        None
+    } else {
+        Some(title.to_string())
+    };
+    Some((title, color))
+}
+
+/// Result of parsing a multiline note start
+struct NoteStart {
+    position: NotePosition,
+    shape: NoteShape,
+    participants: Vec<String>,
+    color: Option<String>,
+}
+
+/// `note`, `rnote` or `hnote` at the start of a line, and the text after it
+fn split_note_keyword(input: &str) -> Option<(NoteShape, &str)> {
+    let word_end = input.find(char::is_whitespace).unwrap_or(input.len());
+    let shape = match input[..word_end].to_lowercase().as_str() {
+        "note" => NoteShape::Folded,
+        "rnote" => NoteShape::Rectangle,
+        "hnote" => NoteShape::Hexagon,
+        _ => return None,
+    };
+    Some((shape, input[word_end..].trim()))
+}
+
+/// Whether a line closes a multiline note (`end note`, `end rnote`, `end hnote`)
+fn is_note_end(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    lower
+        .strip_prefix("end")
+        .filter(|rest| rest.starts_with(char::is_whitespace))
+        .and_then(|rest| split_note_keyword(rest.trim_start()))
+        .map_or(false, |(_, rest)| rest.is_empty())
+}
+
+/// Check if line starts a multiline note (note without colon)
+fn parse_multiline_note_start(input: &str) -> Option<NoteStart> {
+    // Must start with a note keyword but not have a colon
+    if input.contains(':') {
+        return None;
+    }
+    let (shape, rest) = split_note_keyword(input)?;
+
+    let (rest, color) = match rest.rfind(char::is_whitespace) {
+        Some(pos) if is_color_token(&rest[pos + 1..]) => (rest[..pos].trim_end(), Some(rest[pos + 1..].to_string())),
+        _ => (rest, None),
+    };
 
     // Determine position
     let (position, after_pos) = if rest.to_lowercase().starts_with("left of ") {
@@ -247,6 +805,13 @@ fn parse_multiline_note_start(input: &str) -> Option<(NotePosition, Vec<String>)
         (NotePosition::Right, &rest[9..])
     } else if rest.to_lowercase().starts_with("over ") {
         (NotePosition::Over, &rest[5..])
+    } else if rest.eq_ignore_ascii_case("across") {
+        return Some(NoteStart {
+            position: NotePosition::Across,
+            shape,
+            participants: Vec::new(),
+            color,
+        });
     } else {
         return None;
     };
@@ -262,7 +827,12 @@ fn parse_multiline_note_start(input: &str) -> Option<(NotePosition, Vec<String>)
         return None;
     }
 
-    Some((position, participants))
+    Some(NoteStart {
+        position,
+        shape,
+        participants,
+        color,
+    })
 }
 
 /// Result of parsing a multiline ref start
@@ -397,6 +967,9 @@ fn parse_brace_block_start(input: &str) -> Option<(BlockKind, &str)> {
 /// Parse a single line
 fn parse_line(input: &str) -> IResult<&str, Item> {
     alt((
+        parse_divider,
+        parse_spacer,
+        parse_delay,
         parse_state,
         parse_ref_single_line,
         parse_option,
@@ -405,6 +978,7 @@ fn parse_line(input: &str) -> IResult<&str, Item> {
         parse_activate,
         parse_deactivate,
         parse_destroy,
+        parse_create,
         parse_autonumber,
         parse_block_keyword,
         parse_message,
@@ -412,6 +986,63 @@ fn parse_line(input: &str) -> IResult<&str, Item> {
     .parse(input)
 }
 
+/// Whether a whole line is a message, for keywords that can also start a
+/// participant name (`Entity Service->DB: query`)
+fn is_message_line(line: &str) -> bool {
+    matches!(parse_message(line), Ok((_, Item::Message { .. })))
+}
+
+/// Parse a section divider: `== Phase 2 ==`
+fn parse_divider(input: &str) -> IResult<&str, Item> {
+    let (input, _) = tag("==").parse(input)?;
+    let text = input.trim_end().strip_suffix("==").ok_or_else(|| {
+        nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag))
+    })?;
+    Ok((
+        "",
+        Item::Divider {
+            text: text.trim_matches(|c: char| c == '=' || c.is_whitespace()).to_string(),
+            span: Span::default(),
+        },
+    ))
+}
+
+/// Parse a spacer: `|||` or `||45||`
+fn parse_spacer(input: &str) -> IResult<&str, Item> {
+    let (input, _) = tag("||").parse(input)?;
+    let height = if input == "|" {
+        None
+    } else {
+        let inner = input.strip_suffix("||").ok_or_else(|| {
+            nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag))
+        })?;
+        let height = inner.trim().parse::<u32>().map_err(|_| {
+            nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Digit))
+        })?;
+        Some(height)
+    };
+    Ok(("", Item::Spacer { height, span: Span::default() }))
+}
+
+/// Parse a delay: `...` or `... 5 min later ...`
+fn parse_delay(input: &str) -> IResult<&str, Item> {
+    let (input, _) = tag("...").parse(input)?;
+    let text = if input.is_empty() {
+        ""
+    } else {
+        input.strip_suffix("...").ok_or_else(|| {
+            nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag))
+        })?
+    };
+    Ok((
+        "",
+        Item::Delay {
+            text: text.trim().to_string(),
+            span: Span::default(),
+        },
+    ))
+}
+
 /// Parse title
 fn parse_title(input: &str) -> IResult<&str, String> {
     let (input, _) = tag_no_case("title").parse(input)?;
@@ -423,14 +1054,34 @@ fn parse_title(input: &str) -> IResult<&str, String> {
 /// Parse participant declaration: `participant Name` or `actor Name` or `participant "Long Name" as L`
 /// Also supports unquoted names with spaces: `participant OSD Frontend`
 fn parse_participant_decl(input: &str) -> IResult<&str, Item> {
+    let line = input;
     let (input, kind) = alt((
         value(ParticipantKind::Participant, tag_no_case("participant")),
         value(ParticipantKind::Actor, tag_no_case("actor")),
+        value(ParticipantKind::Database, tag_no_case("database")),
+        value(ParticipantKind::Queue, tag_no_case("queue")),
+        value(ParticipantKind::Boundary, tag_no_case("boundary")),
+        value(ParticipantKind::Control, tag_no_case("control")),
+        value(ParticipantKind::Entity, tag_no_case("entity")),
+        value(ParticipantKind::Collections, tag_no_case("collections")),
     ))
     .parse(input)?;
 
     let (input, _) = space1.parse(input)?;
 
+    // `Queue -> Worker: job` and `Entity Service->DB: query` are messages from
+    // participants named like a keyword
+    let is_shape = !matches!(kind, ParticipantKind::Participant | ParticipantKind::Actor);
+    if input.starts_with('-') || (is_shape && is_message_line(line)) {
+        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+    }
+
+    // Trailing style overrides: `#color` or `[key=value, ...]`
+    let (input, style) = match split_participant_style(input) {
+        Some(split) => split,
+        None => return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify))),
+    };
+
     // Parse name - check for quoted name first, then unquoted (possibly with spaces)
     let (remaining, name, alias) = if input.starts_with('"') {
         // Quoted name
@@ -464,10 +1115,88 @@ fn parse_participant_decl(input: &str) -> IResult<&str, Item> {
             name: name.to_string(),
             alias: alias.map(|s| s.to_string()),
             kind,
+            style,
+            span: Span::default(),
         },
     ))
 }
 
+/// Split trailing style overrides off a participant declaration
+///
+/// Returns `None` if a `[...]` style list is malformed.
+fn split_participant_style(input: &str) -> Option<(&str, ParticipantStyle)> {
+    let trimmed = input.trim_end();
+    let mut style = ParticipantStyle::default();
+
+    if trimmed.ends_with(']') {
+        let open = trimmed.rfind('[')?;
+        for entry in trimmed[open + 1..trimmed.len() - 1].split(',') {
+            let (key, value) = entry.split_once('=')?;
+            let value = value.trim();
+            if !is_style_color(value) {
+                return None;
+            }
+            let slot = match key.trim().to_lowercase().as_str() {
+                "fill" => &mut style.fill,
+                "stroke" => &mut style.stroke,
+                "text" => &mut style.text,
+                "lifeline" => &mut style.lifeline,
+                _ => return None,
+            };
+            *slot = Some(value.to_string());
+        }
+        return Some((trimmed[..open].trim_end(), style));
+    }
+
+    if let Some(pos) = trimmed.rfind(char::is_whitespace) {
+        let token = &trimmed[pos + 1..];
+        if is_color_token(token) {
+            style.fill = Some(token.to_string());
+            return Some((trimmed[..pos].trim_end(), style));
+        }
+    }
+    Some((trimmed, style))
+}
+
+/// CSS named colors, accepted after `#` (`#lightblue`)
+const CSS_COLOR_NAMES: &[&str] = &[
+    "aliceblue", "antiquewhite", "aqua", "aquamarine", "azure", "beige", "bisque", "black", "blanchedalmond",
+    "blue", "blueviolet", "brown", "burlywood", "cadetblue", "chartreuse", "chocolate", "coral",
+    "cornflowerblue", "cornsilk", "crimson", "cyan", "darkblue", "darkcyan", "darkgoldenrod", "darkgray",
+    "darkgreen", "darkgrey", "darkkhaki", "darkmagenta", "darkolivegreen", "darkorange", "darkorchid",
+    "darkred", "darksalmon", "darkseagreen", "darkslateblue", "darkslategray", "darkslategrey",
+    "darkturquoise", "darkviolet", "deeppink", "deepskyblue", "dimgray", "dimgrey", "dodgerblue", "firebrick",
+    "floralwhite", "forestgreen", "fuchsia", "gainsboro", "ghostwhite", "gold", "goldenrod", "gray", "green",
+    "greenyellow", "grey", "honeydew", "hotpink", "indianred", "indigo", "ivory", "khaki", "lavender",
+    "lavenderblush", "lawngreen", "lemonchiffon", "lightblue", "lightcoral", "lightcyan",
+    "lightgoldenrodyellow", "lightgray", "lightgreen", "lightgrey", "lightpink", "lightsalmon",
+    "lightseagreen", "lightskyblue", "lightslategray", "lightslategrey", "lightsteelblue", "lightyellow",
+    "lime", "limegreen", "linen", "magenta", "maroon", "mediumaquamarine", "mediumblue", "mediumorchid",
+    "mediumpurple", "mediumseagreen", "mediumslateblue", "mediumspringgreen", "mediumturquoise",
+    "mediumvioletred", "midnightblue", "mintcream", "mistyrose", "moccasin", "navajowhite", "navy", "oldlace",
+    "olive", "olivedrab", "orange", "orangered", "orchid", "palegoldenrod", "palegreen", "paleturquoise",
+    "palevioletred", "papayawhip", "peru", "pink", "plum", "powderblue", "purple", "rebeccapurple", "red",
+    "rosybrown", "royalblue", "saddlebrown", "salmon", "sandybrown", "seagreen", "seashell", "sienna",
+    "silver", "skyblue", "slateblue", "slategray", "slategrey", "snow", "springgreen", "steelblue", "tan",
+    "teal", "thistle", "tomato", "turquoise", "violet", "wheat", "white", "whitesmoke", "yellow",
+    "yellowgreen", "transparent",
+];
+
+/// `#` followed by a hex color (`#c00`, `#ffe0e0`) or a CSS color name (`#lightblue`)
+fn is_color_token(token: &str) -> bool {
+    let Some(color) = token.strip_prefix('#') else {
+        return false;
+    };
+    let is_hex = matches!(color.len(), 3 | 4 | 6 | 8) && color.chars().all(|c| c.is_ascii_hexdigit());
+    let is_name = CSS_COLOR_NAMES.iter().any(|name| name.eq_ignore_ascii_case(color));
+    is_hex || is_name
+}
+
+/// A color in a `[key=value]` style list: a color token or a bare name or hex (`red`, `c00`)
+fn is_style_color(value: &str) -> bool {
+    is_color_token(value) || (!value.starts_with('#') && is_color_token(&format!("#{}", value)))
+}
+
 /// Parse a name (quoted or unquoted) - Task 6: supports colon in quoted names
 fn parse_name(input: &str) -> IResult<&str, &str> {
     alt((
@@ -487,18 +1216,98 @@ fn parse_identifier(input: &str) -> IResult<&str, &str> {
     take_while1(|c: char| c.is_alphanumeric() || c == '_').parse(input)
 }
 
+/// Parse a message, with optional inline style: `A-[#red]>B: text` or
+/// `A->B: text [color=red, bold]`
+fn parse_message(input: &str) -> IResult<&str, Item> {
+    let (plain, message_style) = split_message_style(input)
+        .ok_or_else(|| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)))?;
+    let (_, mut item) =
+        parse_plain_message(&plain).map_err(|e| e.map(|e| nom::error::Error::new(input, e.code)))?;
+    if let Item::Message { style, .. } = &mut item {
+        *style = message_style;
+    }
+    Ok(("", item))
+}
+
+/// Split message style overrides from a message line
+///
+/// A style list inside the arrow (`-[...]>`) must be valid. A trailing
+/// `[...]` is only taken as a style when every entry is recognized, so
+/// message text such as `items[0]` is left alone.
+fn split_message_style(input: &str) -> Option<(String, MessageStyle)> {
+    let mut style = MessageStyle::default();
+    let mut plain = input.to_string();
+
+    let text_start = input.find(':').unwrap_or(input.len());
+    if let Some(open) = input[..text_start].find("-[") {
+        let close = open + input[open..].find(']')?;
+        if !input[close + 1..].starts_with('>') {
+            return None;
+        }
+        parse_message_style_list(&input[open + 2..close], &mut style)?;
+        plain = format!("{}{}", &input[..open + 1], &input[close + 1..]);
+    }
+
+    let trimmed = plain.trim_end();
+    if trimmed.ends_with(']') {
+        if let Some(open) = trimmed.rfind('[') {
+            let mut trailing = style.clone();
+            if parse_message_style_list(&trimmed[open + 1..trimmed.len() - 1], &mut trailing).is_some() {
+                style = trailing;
+                plain = trimmed[..open].trim_end().to_string();
+            }
+        }
+    }
+    Some((plain, style))
+}
+
+/// Parse `#color`, `bold` and `key=value` entries (keys `color`, `width`, `text`)
+fn parse_message_style_list(list: &str, style: &mut MessageStyle) -> Option<()> {
+    for entry in list.split(',') {
+        let entry = entry.trim();
+        if entry.eq_ignore_ascii_case("bold") {
+            style.width = Some(MessageStyle::BOLD_WIDTH);
+            continue;
+        }
+        if is_color_token(entry) {
+            style.color = Some(entry.to_string());
+            continue;
+        }
+        let (key, value) = entry.split_once('=')?;
+        let value = value.trim();
+        match key.trim().to_lowercase().as_str() {
+            "color" | "text" if !is_style_color(value) => return None,
+            "color" => style.color = Some(value.to_string()),
+            "text" => style.text = Some(value.to_string()),
+            "width" => {
+                let width: f64 = value.parse().ok()?;
+                if width <= 0.0 || !width.is_finite() {
+                    return None;
+                }
+                style.width = Some(width);
+            }
+            _ => return None,
+        }
+    }
+    Some(())
+}
+
 /// Parse a message: `A->B: text` or `A->>B: text` etc.
 /// Task 6: Now supports quoted names with colons
 /// Also supports unquoted names with spaces: `OSD Frontend->OSD Backend: text`
-fn parse_message(input: &str) -> IResult<&str, Item> {
+fn parse_plain_message(input: &str) -> IResult<&str, Item> {
     // Arrow patterns to search for (ordered by length, longest first)
     let arrow_patterns = [
-        ("<-->", Arrow::RESPONSE),
+        ("<-->", Arrow { start_head: Some(ArrowHead::Filled), ..Arrow::RESPONSE }),
         ("-->>", Arrow::RESPONSE_OPEN),
-        ("<->", Arrow::SYNC),
+        ("<->", Arrow { start_head: Some(ArrowHead::Filled), ..Arrow::SYNC }),
         ("-->", Arrow::RESPONSE),
         ("->>", Arrow::SYNC_OPEN),
         ("->", Arrow::SYNC),
+        ("--\\", Arrow { head: ArrowHead::HalfUpper, ..Arrow::RESPONSE }),
+        ("--/", Arrow { head: ArrowHead::HalfLower, ..Arrow::RESPONSE }),
+        ("-\\", Arrow { head: ArrowHead::HalfUpper, ..Arrow::SYNC }),
+        ("-/", Arrow { head: ArrowHead::HalfLower, ..Arrow::SYNC }),
     ];
 
     // Find the arrow position and type
@@ -509,29 +1318,52 @@ fn parse_message(input: &str) -> IResult<&str, Item> {
         if let Some(paren_end) = input[delay_start + 3..].find(')') {
             let delay_str = &input[delay_start + 3..delay_start + 3 + paren_end];
             if let Ok(delay_val) = delay_str.parse::<u32>() {
-                arrow_pos = Some((delay_start, delay_start + 4 + paren_end, Arrow {
+                let bidirectional = input[..delay_start].ends_with('<');
+                let start = if bidirectional { delay_start - 1 } else { delay_start };
+                arrow_pos = Some((start, delay_start + 4 + paren_end, Arrow {
                     line: LineStyle::Solid,
                     head: ArrowHead::Filled,
+                    start_head: bidirectional.then_some(ArrowHead::Filled),
                     delay: Some(delay_val),
                 }));
             }
         }
     }
 
-    // If no delayed arrow, search for regular arrows
+    // If no delayed arrow, take the leftmost regular arrow (longest on a tie)
     if arrow_pos.is_none() {
         for (pattern, arrow) in &arrow_patterns {
             if let Some(pos) = input.find(pattern) {
-                arrow_pos = Some((pos, pos + pattern.len(), *arrow));
-                break;
+                if arrow_pos.map_or(true, |(start, _, _)| pos < start) {
+                    arrow_pos = Some((pos, pos + pattern.len(), *arrow));
+                }
             }
         }
     }
 
-    let (arrow_start, arrow_end, arrow) = arrow_pos.ok_or_else(|| {
+    let (mut arrow_start, mut arrow_end, mut arrow) = arrow_pos.ok_or_else(|| {
         nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag))
     })?;
 
+    // Cross or circle endpoints, separated from the names by whitespace: `A ->x B`, `A o-> B`
+    if input[..arrow_end].ends_with('>') {
+        let rest = &input[arrow_end..];
+        for (marker, head) in [('x', ArrowHead::Cross), ('o', ArrowHead::Circle)] {
+            if rest.starts_with(marker) && rest[1..].starts_with(char::is_whitespace) {
+                arrow.head = head;
+                arrow_end += 1;
+            }
+        }
+    }
+    if arrow.start_head.is_none() {
+        if let Some(before) = input[..arrow_start].strip_suffix('o') {
+            if before.ends_with(char::is_whitespace) && !before.trim().is_empty() {
+                arrow.start_head = Some(ArrowHead::Circle);
+                arrow_start -= 1;
+            }
+        }
+    }
+
     // Split: from is before arrow, rest is after arrow
     let from = input[..arrow_start].trim();
     let after_arrow = &input[arrow_end..];
@@ -545,7 +1377,10 @@ fn parse_message(input: &str) -> IResult<&str, Item> {
         let trimmed = after_mods.trim_start();
         if let Some(end_quote) = trimmed[1..].find('"') {
             let to_name = &trimmed[1..end_quote + 1];
-            let rest = &trimmed[end_quote + 2..];
+            let rest = trimmed[end_quote + 2..].trim_start();
+            if !rest.is_empty() && !rest.starts_with(':') {
+                return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char)));
+            }
             let text = rest.trim_start_matches(':').trim().to_string();
             (to_name, text)
         } else {
@@ -570,6 +1405,10 @@ fn parse_message(input: &str) -> IResult<&str, Item> {
         from
     };
 
+    if from.is_empty() || to.is_empty() {
+        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+    }
+
     Ok((
         "",
         Item::Message {
@@ -577,74 +1416,99 @@ fn parse_message(input: &str) -> IResult<&str, Item> {
             to: to.to_string(),
             text,
             arrow,
-            activate: modifiers.0,
-            deactivate: modifiers.1,
-            create: modifiers.2,
+            activate: modifiers.activate,
+            activation_color: modifiers.activation_color,
+            deactivate: modifiers.deactivate,
+            create: modifiers.create,
+            style: MessageStyle::default(),
+            note: None,
+            span: Span::default(),
         },
     ))
 }
 
-/// Parse arrow: `->`, `->>`, `-->`, `-->>`, `->(n)`, `<->`, `<-->`
-/// Task 9: Added bidirectional arrow support (though WSD may not use it)
-fn parse_arrow(input: &str) -> IResult<&str, Arrow> {
-    alt((
-        // <--> bidirectional dashed (if needed)
-        value(Arrow::RESPONSE, tag("<-->")),
-        // <-> bidirectional solid (if needed)
-        value(Arrow::SYNC, tag("<->")),
-        // -->> dashed open
-        value(Arrow::RESPONSE_OPEN, tag("-->>")),
-        // --> dashed filled
-        value(Arrow::RESPONSE, tag("-->")),
-        // ->> solid open
-        value(Arrow::SYNC_OPEN, tag("->>")),
-        // ->(n) delayed
-        map(delimited(tag("->("), digit1, char(')')), |n: &str| Arrow {
-            line: LineStyle::Solid,
-            head: ArrowHead::Filled,
-            delay: n.parse().ok(),
-        }),
-        // -> solid filled
-        value(Arrow::SYNC, tag("->")),
-    ))
-    .parse(input)
-}
-
 /// Parse arrow modifiers: `+` (activate), `-` (deactivate), `*` (create)
-fn parse_arrow_modifiers(input: &str) -> IResult<&str, (bool, bool, bool)> {
+fn parse_arrow_modifiers(input: &str) -> IResult<&str, ArrowModifiers> {
     let (input, mods) = take_while(|c| c == '+' || c == '-' || c == '*').parse(input)?;
     let activate = mods.contains('+');
-    let deactivate = mods.contains('-');
-    let create = mods.contains('*');
-    Ok((input, (activate, deactivate, create)))
+
+    // Activation color right after `+`: `A->+[#ffcccc]B`
+    let (input, activation_color) = match input.strip_prefix('[') {
+        Some(rest) if activate => {
+            let (color, rest) = rest.split_once(']').ok_or_else(|| {
+                nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Char))
+            })?;
+            let color = color.trim();
+            if !is_color_token(color) {
+                return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+            }
+            (rest, Some(color.to_string()))
+        }
+        _ => (input, None),
+    };
+
+    Ok((
+        input,
+        ArrowModifiers {
+            activate,
+            deactivate: mods.contains('-'),
+            create: mods.contains('*'),
+            activation_color,
+        },
+    ))
+}
+
+/// `+`, `-` and `*` after an arrow
+struct ArrowModifiers {
+    activate: bool,
+    deactivate: bool,
+    create: bool,
+    activation_color: Option<String>,
 }
 
-/// Parse note: `note left of A: text`, `note right of A: text`, `note over A: text`, `note over A,B: text`
+/// Parse note: `note left of A: text`, `note right of A: text`, `note over A: text`, `note over A,B: text`,
+/// `note across: text`, with `rnote`/`hnote` for other shapes and an optional `#color` before the colon
 fn parse_note(input: &str) -> IResult<&str, Item> {
-    let (input, _) = tag_no_case("note").parse(input)?;
+    let (input, shape) = alt((
+        value(NoteShape::Folded, tag_no_case("note")),
+        value(NoteShape::Rectangle, tag_no_case("rnote")),
+        value(NoteShape::Hexagon, tag_no_case("hnote")),
+    ))
+    .parse(input)?;
     let (input, _) = space1.parse(input)?;
 
     let (input, position) = alt((
         value(NotePosition::Left, pair(tag_no_case("left"), space1)),
         value(NotePosition::Right, pair(tag_no_case("right"), space1)),
+        value(NotePosition::Across, tag_no_case("across")),
         value(NotePosition::Over, tag_no_case("")),
     ))
     .parse(input)?;
 
-    let (input, position) = if position == NotePosition::Over {
-        let (input, _) = tag_no_case("over").parse(input)?;
-        (input, NotePosition::Over)
+    let (input, participants) = if position == NotePosition::Across {
+        if input.starts_with(|c: char| c.is_alphanumeric()) {
+            return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)));
+        }
+        (input, Vec::new())
     } else {
-        let (input, _) = tag_no_case("of").parse(input)?;
-        (input, position)
-    };
+        let keyword = if position == NotePosition::Over { "over" } else { "of" };
+        let (input, _) = tag_no_case(keyword).parse(input)?;
+        let (input, _) = space1.parse(input)?;
 
-    let (input, _) = space1.parse(input)?;
+        // Parse participants (comma-separated) - support quoted names
+        separated_list1((space0, char(','), space0), parse_name).parse(input)?
+    };
 
-    // Parse participants (comma-separated) - support quoted names
-    let (input, participants) =
-        separated_list1((space0, char(','), space0), parse_name).parse(input)?;
+    let (input, color) = opt(preceded(
+        space0,
+        recognize(pair(char('#'), take_while1(|c: char| !c.is_whitespace() && c != ':'))),
+    ))
+    .parse(input)?;
+    if color.map_or(false, |color| !is_color_token(color)) {
+        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+    }
 
+    let (input, _) = space0.parse(input)?;
     let (input, _) = opt(char(':')).parse(input)?;
     let (input, _) = space0.parse(input)?;
     let text = input.trim().to_string();
@@ -653,8 +1517,11 @@ fn parse_note(input: &str) -> IResult<&str, Item> {
         "",
         Item::Note {
             position,
+            shape,
             participants: participants.into_iter().map(|s| s.to_string()).collect(),
             text,
+            color: color.map(|c| c.to_string()),
+            span: Span::default(),
         },
     ))
 }
@@ -679,6 +1546,7 @@ fn parse_state(input: &str) -> IResult<&str, Item> {
         Item::State {
             participants: participants.into_iter().map(|s| s.to_string()).collect(),
             text,
+            span: Span::default(),
         },
     ))
 }
@@ -707,6 +1575,7 @@ fn parse_ref_single_line(input: &str) -> IResult<&str, Item> {
             input_label: None,
             output_to: None,
             output_label: None,
+            span: Span::default(),
         },
     ))
 }
@@ -724,19 +1593,31 @@ fn parse_option(input: &str) -> IResult<&str, Item> {
         Item::DiagramOption {
             key: key.to_string(),
             value: value.to_string(),
+            span: Span::default(),
         },
     ))
 }
 
-/// Parse activate: `activate A`
+/// Parse activate: `activate A` or `activate A #color`
 fn parse_activate(input: &str) -> IResult<&str, Item> {
     let (input, _) = tag_no_case("activate").parse(input)?;
     let (input, _) = space1.parse(input)?;
-    let (_input, participant) = parse_name(input)?;
+    let (input, participant) = parse_name(input)?;
+    let rest = input.trim();
+    let color = if rest.starts_with('#') {
+        if !is_color_token(rest) {
+            return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+        }
+        Some(rest.to_string())
+    } else {
+        None
+    };
     Ok((
         "",
         Item::Activate {
             participant: participant.to_string(),
+            color,
+            span: Span::default(),
         },
     ))
 }
@@ -750,6 +1631,7 @@ fn parse_deactivate(input: &str) -> IResult<&str, Item> {
         "",
         Item::Deactivate {
             participant: participant.to_string(),
+            span: Span::default(),
         },
     ))
 }
@@ -758,29 +1640,87 @@ fn parse_deactivate(input: &str) -> IResult<&str, Item> {
 fn parse_destroy(input: &str) -> IResult<&str, Item> {
     let (input, _) = tag_no_case("destroy").parse(input)?;
     let (input, _) = space1.parse(input)?;
-    let (_input, participant) = parse_name(input)?;
+    let (_input, participant) = parse_name(input)?;
+    Ok((
+        "",
+        Item::Destroy {
+            participant: participant.to_string(),
+            span: Span::default(),
+        },
+    ))
+}
+
+/// Parse create: `create A`
+fn parse_create(input: &str) -> IResult<&str, Item> {
+    let (input, _) = tag_no_case("create").parse(input)?;
+    let (input, _) = space1.parse(input)?;
+    // `Create Account->DB: insert` is a message from a participant named `Create Account`
+    let (_, participant) = terminated(parse_name, (space0, eof)).parse(input)?;
     Ok((
         "",
-        Item::Destroy {
+        Item::Create {
             participant: participant.to_string(),
+            span: Span::default(),
         },
     ))
 }
 
-/// Parse autonumber: `autonumber` or `autonumber off` or `autonumber 5`
+/// Parse autonumber: `autonumber [start] [step] ["format"]`, `autonumber off`,
+/// `autonumber resume [step] ["format"]` or `autonumber inc A`
 fn parse_autonumber(input: &str) -> IResult<&str, Item> {
     let (input, _) = tag_no_case("autonumber").parse(input)?;
+    let (input, _) = keyword_end(input)?;
+    let invalid = || nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify));
+
+    // The format is a trailing quoted string
+    let (args, format) = match input.find('"') {
+        Some(open) => {
+            let quoted = input[open + 1..].trim_end().strip_suffix('"').ok_or_else(invalid)?;
+            (&input[..open], Some(quoted.to_string()))
+        }
+        None => (input, None),
+    };
+    let args: Vec<&str> = args.split_whitespace().collect();
+    let number = |arg: &str| arg.parse::<u32>().map_err(|_| invalid());
 
-    let (_input, rest) =
-        opt(preceded(space1, take_while1(|c: char| !c.is_whitespace()))).parse(input)?;
-
-    let (enabled, start) = match rest {
-        Some("off") => (false, None),
-        Some(n) => (true, n.parse().ok()),
-        None => (true, None),
+    let command = match args.as_slice() {
+        [word] if format.is_none() && (word.eq_ignore_ascii_case("off") || word.eq_ignore_ascii_case("stop")) => {
+            AutonumberCommand::Stop
+        }
+        [word, step @ ..] if word.eq_ignore_ascii_case("resume") && step.len() <= 1 => AutonumberCommand::Resume {
+            step: step.first().map(|s| number(s)).transpose()?,
+            format,
+        },
+        [word, level] if word.eq_ignore_ascii_case("inc") && format.is_none() => {
+            let level = match level.as_bytes() {
+                [c] if c.is_ascii_alphabetic() => (c.to_ascii_uppercase() - b'A') as usize,
+                _ => return Err(invalid()),
+            };
+            AutonumberCommand::Increment { level }
+        }
+        [] | [_] | [_, _] => {
+            let start = match args.first() {
+                Some(start) => start.split('.').map(number).collect::<Result<Vec<u32>, _>>()?,
+                None => vec![1],
+            };
+            let step = args.get(1).map(|s| number(s)).transpose()?.unwrap_or(1);
+            AutonumberCommand::Start { start, step, format }
+        }
+        _ => return Err(invalid()),
     };
 
-    Ok(("", Item::Autonumber { enabled, start }))
+    Ok((
+        "",
+        Item::Autonumber {
+            command,
+            span: Span::default(),
+        },
+    ))
+}
+
+/// Require a keyword to be followed by whitespace or the end of the line
+fn keyword_end(input: &str) -> IResult<&str, &str> {
+    alt((space1, eof)).parse(input)
 }
 
 /// Parse block keywords: alt, opt, loop, par, else, end
@@ -788,19 +1728,65 @@ fn parse_block_keyword(input: &str) -> IResult<&str, Item> {
     alt((parse_block_start, parse_else, parse_end)).parse(input)
 }
 
-/// Parse block start: `alt condition`, `opt condition`, `loop condition`, `par`, `seq`
+/// Parse block start: `alt condition`, `opt condition`, `loop condition`, `par`, `seq`,
+/// and the UML fragments `break`, `critical`, `neg`, `ignore {m1, m2}`,
+/// `consider {m1, m2}`, `assert`, `strict`, `region`, and `group label [condition]`
 fn parse_block_start(input: &str) -> IResult<&str, Item> {
+    let line = input;
     let (input, kind) = alt((
         value(BlockKind::Alt, tag_no_case("alt")),
         value(BlockKind::Opt, tag_no_case("opt")),
         value(BlockKind::Loop, tag_no_case("loop")),
         value(BlockKind::Par, tag_no_case("par")),
         value(BlockKind::Seq, tag_no_case("seq")),
+        value(BlockKind::Break, tag_no_case("break")),
+        value(BlockKind::Critical, tag_no_case("critical")),
+        value(BlockKind::Neg, tag_no_case("neg")),
+        value(BlockKind::Ignore, tag_no_case("ignore")),
+        value(BlockKind::Consider, tag_no_case("consider")),
+        value(BlockKind::Assert, tag_no_case("assert")),
+        value(BlockKind::Strict, tag_no_case("strict")),
+        value(BlockKind::Region, tag_no_case("region")),
+        value(BlockKind::Group, tag_no_case("group")),
     ))
     .parse(input)?;
 
-    let (input, _) = space0.parse(input)?;
-    let label = input.trim().to_string();
+    let (input, _) = alt((keyword_end, peek_brace)).parse(input)?;
+    let input = input.trim();
+
+    // `Region -> Bob: hi` and `Critical Path->X: a` are messages from participants named like a keyword
+    let is_fragment = !matches!(
+        kind,
+        BlockKind::Alt | BlockKind::Opt | BlockKind::Loop | BlockKind::Par | BlockKind::Seq
+    );
+    if input.starts_with('-') || (is_fragment && is_message_line(line)) {
+        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+    }
+
+    // ignore/consider take a `{msg1, msg2}` list before the optional label
+    let (messages, label) = if matches!(kind, BlockKind::Ignore | BlockKind::Consider) {
+        let (rest, list) = opt(delimited(char('{'), take_until("}"), char('}'))).parse(input)?;
+        let messages = list
+            .map(|list| {
+                list.split(',')
+                    .map(|m| m.trim().to_string())
+                    .filter(|m| !m.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        (messages, rest.trim().to_string())
+    } else {
+        (Vec::new(), input.to_string())
+    };
+
+    // group takes a free-form label with an optional trailing `[condition]`
+    let (label, condition) = match label.rfind('[') {
+        Some(open) if kind == BlockKind::Group && label.ends_with(']') => (
+            label[..open].trim().to_string(),
+            Some(label[open + 1..label.len() - 1].trim().to_string()),
+        ),
+        _ => (label, None),
+    };
 
     // Return a marker block that will be processed later
     Ok((
@@ -808,16 +1794,24 @@ fn parse_block_start(input: &str) -> IResult<&str, Item> {
         Item::Block {
             kind,
             label,
+            messages,
+            condition,
             items: vec![],
             else_sections: vec![],
+            span: Span::default(),
         },
     ))
 }
 
+/// Zero-width match of `{`, so `ignore{a}` needs no space after the keyword
+fn peek_brace(input: &str) -> IResult<&str, &str> {
+    peek(tag("{")).parse(input)
+}
+
 /// Parse else: `else condition`
 fn parse_else(input: &str) -> IResult<&str, Item> {
     let (input, _) = tag_no_case("else").parse(input)?;
-    let (input, _) = space0.parse(input)?;
+    let (input, _) = keyword_end(input)?;
     let label = input.trim().to_string();
 
     // Return a marker that will be processed during block building
@@ -826,8 +1820,11 @@ fn parse_else(input: &str) -> IResult<&str, Item> {
         Item::Block {
             kind: BlockKind::Alt, // marker
             label: format!("__ELSE__{}", label),
+            messages: vec![],
+            condition: None,
             items: vec![],
             else_sections: vec![],
+            span: Span::default(),
         },
     ))
 }
@@ -842,52 +1839,308 @@ fn parse_end(input: &str) -> IResult<&str, Item> {
             nom::error::ErrorKind::Tag,
         )));
     }
-    let (_input, _) = tag_no_case("end").parse(input)?;
+    let (input, _) = tag_no_case("end").parse(input)?;
+    let (_input, _) = keyword_end(input)?;
     Ok((
         "",
         Item::Block {
             kind: BlockKind::Alt, // marker
             label: "__END__".to_string(),
+            messages: vec![],
+            condition: None,
             items: vec![],
             else_sections: vec![],
+            span: Span::default(),
         },
     ))
 }
 
+/// Keywords that start a statement, used to suggest fixes for typos
+const KEYWORDS: &[&str] = &[
+    "participant", "actor", "note", "rnote", "hnote", "activate", "deactivate", "destroy", "create", "autonumber", "alt", "opt",
+    "loop", "par", "seq", "else", "end", "title", "option", "state", "ref", "parallel", "serial",
+    "database", "queue", "boundary", "control", "entity", "collections", "break", "critical", "neg", "ignore", "consider", "assert", "strict", "region", "group", "box",
+];
+
+/// Span of the byte range `start..end` within a single-line span whose text is `text`
+fn subspan(span: Span, text: &str, start: usize, end: usize) -> Span {
+    let column = span.column + text[..start].chars().count();
+    Span {
+        start: span.start + start,
+        end: span.start + end,
+        line: span.line,
+        column,
+        end_line: span.line,
+        end_column: column + text[start..end].chars().count(),
+    }
+}
+
+/// Case-insensitive Levenshtein distance between two words
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = if a[i - 1].eq_ignore_ascii_case(&b[j - 1]) { 0 } else { 1 };
+            current[j] = (prev[j] + 1).min(current[j - 1] + 1).min(prev[j - 1] + cost);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+/// Explain why `parse_line` rejected a line
+fn diagnose_line(line: &str, span: Span) -> Diagnostic {
+    let word_end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let word = line[..word_end].to_lowercase();
+    let rest = line[word_end..].trim();
+    let whole = span;
+
+    if line == "}" {
+        return Diagnostic::error(whole, "`}` without a matching `parallel {` or `serial {`")
+            .with_suggestion("remove this `}`", Some(String::new()));
+    }
+
+    // Directives the preprocessor doesn't know are passed through as text
+    if let Some(name) = line.strip_prefix('!') {
+        let end = name.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(name.len());
+        if end > 0 {
+            return Diagnostic::error(whole, format!("unknown directive `!{}`", &name[..end]))
+                .with_suggestion("use `!include`, `!define`, `!undef`, `!if`, `!else`, `!endif` or `!procedure`", None);
+        }
+    }
+
+    if line.starts_with("||") {
+        return Diagnostic::error(whole, "invalid spacer")
+            .with_suggestion("use `|||` for one row or `||45||` for a height in pixels", None);
+    }
+    if line.starts_with("...") {
+        return Diagnostic::error(whole, "unterminated delay")
+            .with_suggestion("close the delay text with `...`", Some(format!("{} ...", line)));
+    }
+    if line.starts_with("==") {
+        return Diagnostic::error(whole, "unterminated divider")
+            .with_suggestion("close the divider with `==`", Some(format!("{} ==", line)));
+    }
+
+    if let Some(open) = line.find("+[") {
+        if let Some(close) = line[open..].find(']').map(|pos| open + pos) {
+            if !is_color_token(line[open + 2..close].trim()) {
+                return Diagnostic::error(subspan(span, line, open + 1, close + 1), "invalid activation color")
+                    .with_suggestion("use `->+[#color]`, e.g. `Alice->+[#ffcccc]Bob: lock`", None);
+            }
+        }
+    }
+    if let Some(open) = line.find("-[") {
+        if let Some(close) = line[open..].find("]>").map(|pos| open + pos) {
+            if split_message_style(line).is_none() {
+                return Diagnostic::error(subspan(span, line, open + 1, close + 1), "invalid message style")
+                    .with_suggestion("use `-[#color]>` or `-[color=#color, width=2, text=#color, bold]>`", None);
+            }
+        }
+    }
+
+    if let Some(arrow_pos) = line.find("->") {
+        let from = line[..arrow_pos].trim();
+        if from.is_empty() {
+            let arrow_span = subspan(span, line, arrow_pos, arrow_pos + 2);
+            return Diagnostic::error(arrow_span, "expected a sender participant before the arrow")
+                .with_suggestion("add the sending participant, e.g. `Alice->Bob: text`", None);
+        }
+        if from.starts_with('"') && (from.len() < 2 || !from.ends_with('"')) {
+            return Diagnostic::error(
+                subspan(span, line, 0, arrow_pos),
+                "unterminated quote in sender participant name",
+            )
+            .with_suggestion("close the quote", Some(format!("{}\"", from)));
+        }
+
+        let after_arrow = arrow_pos + line[arrow_pos..]
+            .find(|c: char| !matches!(c, '-' | '>' | '<' | '+' | '*'))
+            .unwrap_or(line.len() - arrow_pos);
+        let target = line[after_arrow..].trim_start();
+        let target_start = line.len() - target.len();
+        if target.is_empty() || target.starts_with(':') {
+            let at = subspan(span, line, arrow_pos, after_arrow);
+            return Diagnostic::error(at, "expected a target participant after the arrow")
+                .with_suggestion("add the receiving participant, e.g. `Alice->Bob: text`", None);
+        }
+        if let Some(quoted) = target.strip_prefix('"') {
+            match quoted.find('"') {
+                None => {
+                    return Diagnostic::error(
+                        subspan(span, line, target_start, line.len()),
+                        "unterminated quote in target participant name",
+                    )
+                    .with_suggestion("close the quote", None);
+                }
+                Some(end_quote) => {
+                    let after = target_start + 1 + end_quote + 1;
+                    let trailing = line[after..].trim_start();
+                    let trailing_start = line.len() - trailing.len();
+                    return Diagnostic::error(
+                        subspan(span, line, after, line.len()),
+                        "expected `:` after target participant",
+                    )
+                    .with_suggestion(
+                        "separate the message text with a colon",
+                        Some(format!(": {}", &line[trailing_start..])),
+                    );
+                }
+            }
+        }
+        return Diagnostic::error(whole, "invalid message");
+    }
+
+    match word.as_str() {
+        "participant" | "actor" | "database" | "queue" | "boundary" | "control" | "entity" | "collections"
+            if rest.is_empty() =>
+        {
+            return Diagnostic::error(whole, format!("expected a name after `{}`", word))
+                .with_suggestion("name the participant, e.g. `participant Alice`", None);
+        }
+        "participant" | "actor" | "database" | "queue" | "boundary" | "control" | "entity" | "collections"
+            if rest.ends_with(']') =>
+        {
+            let open = line.rfind('[').unwrap_or(0);
+            return Diagnostic::error(subspan(span, line, open, line.len()), "invalid participant style")
+                .with_suggestion("use `[fill=#color, stroke=#color, text=#color, lifeline=#color]`", None);
+        }
+        "note" | "rnote" | "hnote" => {
+            let head_end = line.find(':').unwrap_or(line.len());
+            if let Some(start) = line[..head_end].find('#') {
+                let end = line[start..head_end].find(char::is_whitespace).map_or(head_end, |pos| start + pos);
+                if !is_color_token(&line[start..end]) {
+                    return Diagnostic::error(subspan(span, line, start, end), "invalid note color")
+                        .with_suggestion(format!("e.g. `{} over Alice #lightyellow: text`", word), None);
+                }
+            }
+            let lower = rest.to_lowercase();
+            if word == "note" && lower.starts_with("on link") {
+                return Diagnostic::error(whole, "expected `:` and text after `note on link`")
+                    .with_suggestion("e.g. `note on link: payload schema v2`", None);
+            }
+            let message = if lower.starts_with("left of") || lower.starts_with("right of") || lower.starts_with("over") {
+                "expected a participant name in note".to_string()
+            } else {
+                format!("expected `left of`, `right of`, `over` or `across` after `{}`", word)
+            };
+            return Diagnostic::error(whole, message)
+                .with_suggestion(format!("e.g. `{} over Alice: text`", word), None);
+        }
+        "state" | "ref" => {
+            return Diagnostic::error(whole, format!("expected `over <participants>` after `{}`", word))
+                .with_suggestion(format!("e.g. `{} over Alice: text`", word), None);
+        }
+        "activate" if line.contains('#') => {
+            return Diagnostic::error(whole, "invalid activation color")
+                .with_suggestion("e.g. `activate Alice #ffcccc`", None);
+        }
+        "activate" | "deactivate" | "destroy" | "create" => {
+            return Diagnostic::error(whole, format!("expected a participant name after `{}`", word))
+                .with_suggestion(format!("e.g. `{} Alice`", word), None);
+        }
+        "autonumber" => {
+            return Diagnostic::error(whole, "invalid autonumber")
+                .with_suggestion(
+                    "use `autonumber [start] [step] [\"format\"]`, `autonumber off`, `autonumber resume` or `autonumber inc A`",
+                    None,
+                );
+        }
+        "option" => {
+            return Diagnostic::error(whole, "expected `key=value` after `option`")
+                .with_suggestion("e.g. `option footer=none`", None);
+        }
+        "title" => {
+            return Diagnostic::error(whole, "expected text after `title`");
+        }
+        _ => {}
+    }
+
+    if word.len() >= 4 {
+        if let Some(keyword) = KEYWORDS
+            .iter()
+            .filter(|k| edit_distance(&word, k) <= 2)
+            .min_by_key(|k| edit_distance(&word, k))
+        {
+            return Diagnostic::error(
+                subspan(span, line, 0, word_end),
+                format!("unknown keyword `{}`", &line[..word_end]),
+            )
+            .with_suggestion(format!("did you mean `{}`?", keyword), Some(keyword.to_string()));
+        }
+    }
+
+    if let Some(pos) = line.find("<-") {
+        return Diagnostic::error(
+            subspan(span, line, pos, pos + 2),
+            "left-pointing arrows are not supported",
+        )
+        .with_suggestion("swap the participants and use `->`", None);
+    }
+
+    Diagnostic::error(whole, "expected a message like `Alice->Bob: text` or a statement")
+}
+
 /// Build block structure from flat list of items
-fn build_blocks(items: Vec<Item>) -> Result<Vec<Item>, ParseError> {
+///
+/// Blocks still open at the end of input are closed there; orphan `else` and
+/// `end` markers are dropped. Both are reported as structural diagnostics.
+fn build_blocks(items: Vec<Item>, options: ParseOptions, diagnostics: &mut Vec<Diagnostic>) -> Vec<Item> {
     use crate::ast::ElseSection;
 
     let mut result = Vec::new();
-    // Stack entry: (kind, label, items, else_sections, current_else_items, current_else_label, in_else_branch)
+    // Stack entry: (kind, label, messages, condition, items, else_sections, current_else_items, current_else_label, in_else_branch)
     struct StackEntry {
         kind: BlockKind,
         label: String,
+        messages: Vec<String>,
+        condition: Option<String>,
         items: Vec<Item>,
         else_sections: Vec<ElseSection>,
         current_else_items: Vec<Item>,
         current_else_label: Option<String>,
         in_else_branch: bool,
+        /// Span of the opening keyword line
+        span: Span,
+        /// Span of the current else line
+        current_else_span: Span,
+    }
+
+    impl StackEntry {
+        /// Move the current else branch into `else_sections`
+        fn finish_else(&mut self) {
+            if self.in_else_branch && !self.current_else_items.is_empty() {
+                let last = self.current_else_items.last().map(|item| item.span());
+                let span = last.map_or(self.current_else_span, |last| self.current_else_span.to(last));
+                self.else_sections.push(ElseSection {
+                    label: self.current_else_label.take(),
+                    items: std::mem::take(&mut self.current_else_items),
+                    span,
+                });
+            }
+        }
     }
+
     let mut stack: Vec<StackEntry> = Vec::new();
 
     for item in items {
         match &item {
-            Item::Block { label, .. } if label == "__END__" => {
+            Item::Block { label, span, .. } if label == "__END__" => {
                 // End of block
                 if let Some(mut entry) = stack.pop() {
                     // If we were in an else branch, finalize it
-                    if entry.in_else_branch && !entry.current_else_items.is_empty() {
-                        entry.else_sections.push(ElseSection {
-                            label: entry.current_else_label.take(),
-                            items: std::mem::take(&mut entry.current_else_items),
-                        });
-                    }
+                    entry.finish_else();
                     let block = Item::Block {
                         kind: entry.kind,
                         label: entry.label,
+                        messages: entry.messages,
+                        condition: entry.condition,
                         items: entry.items,
                         else_sections: entry.else_sections,
+                        span: entry.span.to(*span),
                     };
                     if let Some(parent) = stack.last_mut() {
                         if parent.in_else_branch {
@@ -898,35 +2151,43 @@ fn build_blocks(items: Vec<Item>) -> Result<Vec<Item>, ParseError> {
                     } else {
                         result.push(block);
                     }
+                } else {
+                    diagnostics.push(
+                        structural(options, *span, "`end` without a matching block".to_string())
+                            .with_suggestion("remove this `end`", Some(String::new())),
+                    );
                 }
             }
-            Item::Block { label, .. } if label.starts_with("__ELSE__") => {
+            Item::Block { label, span, .. } if label.starts_with("__ELSE__") => {
                 // Else marker - extract the else label
                 let else_label_text = label.strip_prefix("__ELSE__").unwrap_or("").to_string();
                 if let Some(entry) = stack.last_mut() {
                     // If we were already in an else branch, save the current one
-                    if entry.in_else_branch && !entry.current_else_items.is_empty() {
-                        entry.else_sections.push(ElseSection {
-                            label: entry.current_else_label.take(),
-                            items: std::mem::take(&mut entry.current_else_items),
-                        });
-                    }
+                    entry.finish_else();
                     // Start new else branch
                     entry.in_else_branch = true;
                     entry.current_else_items = Vec::new();
+                    entry.current_else_span = *span;
                     entry.current_else_label = if else_label_text.is_empty() {
                         None
                     } else {
                         Some(else_label_text)
                     };
+                } else {
+                    diagnostics.push(
+                        structural(options, *span, "`else` outside of a block".to_string())
+                            .with_suggestion("move this `else` inside an `alt` block", None),
+                    );
                 }
             }
             Item::Block {
                 kind,
                 label,
+                messages,
+                condition,
                 items,
                 else_sections,
-                ..
+                span,
             } if !label.starts_with("__") => {
                 // Check if this is a completed block (parallel/serial with items already)
                 if matches!(kind, BlockKind::Parallel | BlockKind::Serial) || !items.is_empty() {
@@ -934,8 +2195,11 @@ fn build_blocks(items: Vec<Item>) -> Result<Vec<Item>, ParseError> {
                     let block = Item::Block {
                         kind: *kind,
                         label: label.clone(),
+                        messages: messages.clone(),
+                        condition: condition.clone(),
                         items: items.clone(),
                         else_sections: else_sections.clone(),
+                        span: *span,
                     };
                     if let Some(parent) = stack.last_mut() {
                         if parent.in_else_branch {
@@ -951,11 +2215,15 @@ fn build_blocks(items: Vec<Item>) -> Result<Vec<Item>, ParseError> {
                     stack.push(StackEntry {
                         kind: *kind,
                         label: label.clone(),
+                        messages: messages.clone(),
+                        condition: condition.clone(),
                         items: Vec::new(),
                         else_sections: Vec::new(),
                         current_else_items: Vec::new(),
                         current_else_label: None,
                         in_else_branch: false,
+                        span: *span,
+                        current_else_span: Span::default(),
                     });
                 }
             }
@@ -974,7 +2242,43 @@ fn build_blocks(items: Vec<Item>) -> Result<Vec<Item>, ParseError> {
         }
     }
 
-    Ok(result)
+    // Close blocks left open at the end of input, innermost first
+    while let Some(mut entry) = stack.pop() {
+        diagnostics.push(
+            structural(
+                options,
+                entry.span,
+                format!("`{}` block is never closed with `end`", entry.kind.as_str()),
+            )
+            .with_suggestion("add `end` after the last line of the block", None),
+        );
+        entry.finish_else();
+        let last = entry
+            .else_sections
+            .last()
+            .map(|section| section.span)
+            .or_else(|| entry.items.last().map(|item| item.span()));
+        let block = Item::Block {
+            kind: entry.kind,
+            label: entry.label,
+            messages: entry.messages,
+            condition: entry.condition,
+            items: entry.items,
+            else_sections: entry.else_sections,
+            span: last.map_or(entry.span, |last| entry.span.to(last)),
+        };
+        if let Some(parent) = stack.last_mut() {
+            if parent.in_else_branch {
+                parent.current_else_items.push(block);
+            } else {
+                parent.items.push(block);
+            }
+        } else {
+            result.push(block);
+        }
+    }
+
+    result
 }
 
 #[cfg(test)]
@@ -1001,6 +2305,436 @@ mod tests {
         assert_eq!(result.items.len(), 2);
     }
 
+    #[test]
+    fn test_participant_kinds() {
+        let input = "database \"Orders DB\" as DB\nqueue Jobs\nboundary UI\ncontrol Ctl\nentity Order\ncollections Items\nQueue -> Jobs: hi";
+        let result = parse(input).unwrap();
+        let kinds: Vec<_> = result
+            .participants()
+            .iter()
+            .map(|p| (p.id().to_string(), p.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("DB".to_string(), ParticipantKind::Database),
+                ("Jobs".to_string(), ParticipantKind::Queue),
+                ("UI".to_string(), ParticipantKind::Boundary),
+                ("Ctl".to_string(), ParticipantKind::Control),
+                ("Order".to_string(), ParticipantKind::Entity),
+                ("Items".to_string(), ParticipantKind::Collections),
+                ("Queue".to_string(), ParticipantKind::Participant),
+            ]
+        );
+
+        // Keyword-named senders are messages, not declarations
+        let result = parse("Entity Service->DB: query\nDatabase Server->App: x\nControl Panel->X: y").unwrap();
+        assert!(result.items.iter().all(|item| matches!(item, Item::Message { .. })));
+        let names: Vec<_> = result.participants().iter().map(|p| p.name.clone()).collect();
+        assert_eq!(
+            names,
+            vec!["Entity Service", "DB", "Database Server", "App", "Control Panel", "X"]
+        );
+    }
+
+    #[test]
+    fn test_participant_style() {
+        let input = "participant Payment #ffe0e0\nactor \"Ext User\" as U [fill=#eef, stroke=#c00, text=#900, lifeline=red]\nparticipant Issue #42";
+        let result = parse(input).unwrap();
+        let participants = result.participants();
+        assert_eq!(participants[0].name, "Payment");
+        assert_eq!(participants[0].style.fill.as_deref(), Some("#ffe0e0"));
+        assert_eq!(
+            participants[1].style,
+            ParticipantStyle {
+                fill: Some("#eef".to_string()),
+                stroke: Some("#c00".to_string()),
+                text: Some("#900".to_string()),
+                lifeline: Some("red".to_string()),
+            }
+        );
+        assert_eq!(participants[1].id(), "U");
+        // `#42` is not a color, so it stays part of the name
+        assert_eq!(participants[2].name, "Issue #42");
+        assert!(participants[2].style.is_empty());
+
+        let output = parse_with_diagnostics("participant Payment [fill=#fee, size=3]");
+        assert_eq!(output.diagnostics[0].message, "invalid participant style");
+        assert_eq!(output.diagnostics[0].span.column, 21);
+        // Values must be colors, so they cannot smuggle in other CSS
+        let output = parse_with_diagnostics("participant X [fill=url(https://evil.example/x.svg#a); display:none]");
+        assert_eq!(output.diagnostics[0].message, "invalid participant style");
+        assert!(output.diagram.participants().is_empty());
+        let output = parse_with_diagnostics("participant X [stroke=red; opacity: 0]");
+        assert_eq!(output.diagnostics[0].message, "invalid participant style");
+    }
+
+    #[test]
+    fn test_bidirectional_arrows() {
+        let result = parse("A<->B: sync\nA<-->B: reply\nA<->(2)B: slow\nA->B: one way").unwrap();
+        let arrows: Vec<(&str, Arrow)> = result
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Message { from, arrow, .. } => (from.as_str(), *arrow),
+                _ => panic!("Expected Message"),
+            })
+            .collect();
+        assert_eq!(arrows[0], ("A", Arrow { start_head: Some(ArrowHead::Filled), ..Arrow::SYNC }));
+        assert_eq!(arrows[1], ("A", Arrow { start_head: Some(ArrowHead::Filled), ..Arrow::RESPONSE }));
+        assert_eq!(arrows[2].0, "A");
+        assert_eq!(arrows[2].1.start_head, Some(ArrowHead::Filled));
+        assert_eq!(arrows[2].1.delay, Some(2));
+        assert_eq!(arrows[3].1.start_head, None);
+    }
+
+    #[test]
+    fn test_arrowhead_shapes() {
+        let result = parse("A ->x B: lost\nA o-> B: found\nA -->o B\nA-\\B: upper\nA--/B: lower\nA->x: to x\nLeo->Bob\nA->B: a-/b").unwrap();
+        let messages: Vec<(&str, &str, Arrow)> = result
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Message { from, to, arrow, .. } => (from.as_str(), to.as_str(), *arrow),
+                _ => panic!("Expected Message"),
+            })
+            .collect();
+        assert_eq!(messages[0], ("A", "B", Arrow { head: ArrowHead::Cross, ..Arrow::SYNC }));
+        assert_eq!(messages[1], ("A", "B", Arrow { start_head: Some(ArrowHead::Circle), ..Arrow::SYNC }));
+        assert_eq!(messages[2], ("A", "B", Arrow { head: ArrowHead::Circle, ..Arrow::RESPONSE }));
+        assert_eq!(messages[3], ("A", "B", Arrow { head: ArrowHead::HalfUpper, ..Arrow::SYNC }));
+        assert_eq!(messages[4], ("A", "B", Arrow { head: ArrowHead::HalfLower, ..Arrow::RESPONSE }));
+        // Without surrounding whitespace `x` and `o` are names
+        assert_eq!(messages[5], ("A", "x", Arrow::SYNC));
+        assert_eq!(messages[6], ("Leo", "Bob", Arrow::SYNC));
+        // The leftmost arrow wins
+        assert_eq!(messages[7], ("A", "B", Arrow::SYNC));
+    }
+
+    #[test]
+    fn test_message_style() {
+        let result = parse("A-[#red]>B: fail\nA--[color=#c00, width=2]>>B\nA->B: retry [bold, text=#900]\nA->B: items[0]").unwrap();
+        let styles: Vec<(&str, &MessageStyle)> = result
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Message { text, arrow, style, .. } => {
+                    if text.is_empty() {
+                        assert_eq!(*arrow, Arrow::RESPONSE_OPEN);
+                    }
+                    (text.as_str(), style)
+                }
+                _ => panic!("Expected Message"),
+            })
+            .collect();
+        assert_eq!(styles[0].0, "fail");
+        assert_eq!(styles[0].1.color.as_deref(), Some("#red"));
+        assert_eq!(styles[1].1.color.as_deref(), Some("#c00"));
+        assert_eq!(styles[1].1.width, Some(2.0));
+        assert_eq!(styles[2].0, "retry");
+        assert_eq!(styles[2].1.width, Some(MessageStyle::BOLD_WIDTH));
+        assert_eq!(styles[2].1.text.as_deref(), Some("#900"));
+        // Not a style list, so it stays part of the text
+        assert_eq!(styles[3].0, "items[0]");
+        assert!(styles[3].1.is_empty());
+        // `#wontfix` is not a color, so the brackets stay in the label
+        let result = parse("Alice->Bob: Close [#wontfix]").unwrap();
+        assert!(matches!(&result.items[0], Item::Message { text, style, .. } if text == "Close [#wontfix]" && style.is_empty()));
+
+        let output = parse_with_diagnostics("A-[size=3]>B: x");
+        assert_eq!(output.diagnostics[0].message, "invalid message style");
+        assert_eq!(output.diagnostics[0].span.column, 3);
+        // Values must be colors, so they cannot smuggle in other CSS
+        let output = parse_with_diagnostics("A-[color=red; stroke-dasharray: 0]>B: x");
+        assert_eq!(output.diagnostics[0].message, "invalid message style");
+        let result = parse("A->B: x [text=red; opacity: 0]").unwrap();
+        assert!(matches!(&result.items[0], Item::Message { text, style, .. } if text == "x [text=red; opacity: 0]" && style.is_empty()));
+    }
+
+    #[test]
+    fn test_autonumber() {
+        let input = "autonumber\nautonumber 10 5 \"[000]\"\nautonumber 1.1\nautonumber off\nautonumber resume 2\nautonumber inc B";
+        let commands: Vec<AutonumberCommand> = parse(input)
+            .unwrap()
+            .items
+            .into_iter()
+            .map(|item| match item {
+                Item::Autonumber { command, .. } => command,
+                _ => panic!("Expected Autonumber"),
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            vec![
+                AutonumberCommand::Start { start: vec![1], step: 1, format: None },
+                AutonumberCommand::Start { start: vec![10], step: 5, format: Some("[000]".to_string()) },
+                AutonumberCommand::Start { start: vec![1, 1], step: 1, format: None },
+                AutonumberCommand::Stop,
+                AutonumberCommand::Resume { step: Some(2), format: None },
+                AutonumberCommand::Increment { level: 1 },
+            ]
+        );
+
+        let output = parse_with_diagnostics("autonumber inc 7\nautonumber 1 2 3");
+        assert_eq!(output.diagnostics.len(), 2);
+        assert!(output.diagnostics.iter().all(|d| d.message == "invalid autonumber"));
+    }
+
+    #[test]
+    fn test_divider() {
+        let result = parse("A->B: a\n== Phase 2 ==\n==== Setup ====\n== A->B ==\n====").unwrap();
+        let texts: Vec<&str> = result
+            .items
+            .iter()
+            .skip(1)
+            .map(|item| match item {
+                Item::Divider { text, .. } => text.as_str(),
+                _ => panic!("Expected Divider"),
+            })
+            .collect();
+        assert_eq!(texts, vec!["Phase 2", "Setup", "A->B", ""]);
+
+        let output = parse_with_diagnostics("== Phase 2");
+        assert_eq!(output.diagnostics[0].message, "unterminated divider");
+    }
+
+    #[test]
+    fn test_spacer_and_delay() {
+        let result = parse("|||\n||45||\n...\n... 5 min later ...").unwrap();
+        assert_eq!(
+            result.items,
+            vec![
+                Item::Spacer { height: None, span: result.items[0].span() },
+                Item::Spacer { height: Some(45), span: result.items[1].span() },
+                Item::Delay { text: String::new(), span: result.items[2].span() },
+                Item::Delay { text: "5 min later".to_string(), span: result.items[3].span() },
+            ]
+        );
+
+        let output = parse_with_diagnostics("||x||\n... later");
+        assert_eq!(output.diagnostics[0].message, "invalid spacer");
+        assert_eq!(output.diagnostics[1].message, "unterminated delay");
+    }
+
+    #[test]
+    fn test_return() {
+        let input = "Alice->+Bob: call\nBob->+Carol: nested\nreturn data\nactivate Bob\ndeactivate Bob\nreturn\nreturn -> Dave: not a return";
+        let diagram = parse(input).unwrap();
+        let responses: Vec<(&str, &str, &str, bool)> = diagram
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Message {
+                    from,
+                    to,
+                    text,
+                    arrow,
+                    deactivate,
+                    ..
+                } if *arrow == Arrow::RESPONSE => Some((from.as_str(), to.as_str(), text.as_str(), *deactivate)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(responses, vec![("Carol", "Bob", "data", true), ("Bob", "Alice", "", true)]);
+        assert!(matches!(&diagram.items[6], Item::Message { from, to, .. } if from == "return" && to == "Dave"));
+        let output = parse_with_diagnostics("Alice->Handler: call\nactivate Handler\nReturn Handler->Bob: hi");
+        assert!(output.diagnostics.is_empty());
+        assert!(matches!(
+            &output.diagram.items[2],
+            Item::Message { from, to, text, arrow, .. }
+                if from == "Return Handler" && to == "Bob" && text == "hi" && *arrow == Arrow::SYNC
+        ));
+
+        let output = parse_with_diagnostics("Alice->+Bob: call\nBob-->-Alice: done\nreturn\nactivate Carol\nreturn x");
+        let messages: Vec<(usize, &str)> = output
+            .diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![(3, "`return` without an active call"), (5, "`return` cannot tell who called `Carol`")]
+        );
+
+        // Each `else` branch returns from the calls open when the block was entered
+        let input = "A->+B: call\nalt ok\nReturn fine\nelse fail\nRETURN error\nend\nreturn";
+        let output = parse_with_diagnostics(input);
+        assert_eq!(output.diagnostics.len(), 1);
+        assert_eq!(output.diagnostics[0].span.line, 7);
+        match &output.diagram.items[1] {
+            Item::Block { items, else_sections, .. } => {
+                assert!(matches!(&items[0], Item::Message { from, to, text, .. } if from == "B" && to == "A" && text == "fine"));
+                assert!(matches!(&else_sections[0].items[0], Item::Message { from, to, text, .. } if from == "B" && to == "A" && text == "error"));
+            }
+            other => panic!("Expected Block, got {:?}", other),
+        }
+
+        // Calls left open by concurrent branches don't outlive them
+        for input in [
+            "A->+B: call\nparallel {\nB->+C: x\nB->+D: y\n}\nreturn r",
+            "A->+B: call\npar\nB->+C: x\nelse\nB->+D: y\nend\nreturn r",
+        ] {
+            let diagram = parse(input).unwrap();
+            assert!(
+                matches!(diagram.items.last(), Some(Item::Message { from, to, .. }) if from == "B" && to == "A"),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_create() {
+        let result = parse("create Bob\ncreate->Bob: still a message").unwrap();
+        assert_eq!(
+            result.items[0],
+            Item::Create { participant: "Bob".to_string(), span: result.items[0].span() }
+        );
+        assert!(matches!(&result.items[1], Item::Message { from, .. } if from == "create"));
+
+        let output = parse_with_diagnostics("Create Account->DB: insert");
+        assert!(output.diagnostics.is_empty());
+        assert!(matches!(
+            &output.diagram.items[0],
+            Item::Message { from, to, text, .. } if from == "Create Account" && to == "DB" && text == "insert"
+        ));
+
+        let output = parse_with_diagnostics("create\ncreate Bob extra");
+        let lines: Vec<usize> = output.diagnostics.iter().map(|d| d.span.line).collect();
+        assert_eq!(lines, vec![1, 2]);
+        assert_eq!(output.diagnostics[0].message, "expected a participant name after `create`");
+    }
+
+    #[test]
+    fn test_activation_color() {
+        let result = parse("activate Bob #ffcccc\nAlice->+[#lightblue]Carol: lock\nAlice->+Dave: plain").unwrap();
+        assert!(matches!(&result.items[0], Item::Activate { color: Some(c), .. } if c == "#ffcccc"));
+        match &result.items[1] {
+            Item::Message {
+                to,
+                activate,
+                activation_color,
+                ..
+            } => {
+                assert_eq!((to.as_str(), *activate), ("Carol", true));
+                assert_eq!(activation_color.as_deref(), Some("#lightblue"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(&result.items[2], Item::Message { activation_color: None, .. }));
+
+        let output = parse_with_diagnostics("activate Bob #not-a-color\nA->+[red]B: x");
+        assert_eq!(output.diagnostics[0].message, "invalid activation color");
+        assert_eq!(output.diagnostics[1].message, "invalid activation color");
+        assert_eq!(output.diagnostics[1].span.column, 5);
+    }
+
+    #[test]
+    fn test_note_shapes_and_colors() {
+        let input = "note across: Phase 1\nrnote over A, B #yellow: boxed\nhnote left of B: step\nhnote across #c0ffee\nmulti\nend hnote";
+        let result = parse(input).unwrap();
+        let notes: Vec<(NotePosition, NoteShape, &[String], Option<&str>, &str)> = result
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Note {
+                    position,
+                    shape,
+                    participants,
+                    text,
+                    color,
+                    ..
+                } => (*position, *shape, participants.as_slice(), color.as_deref(), text.as_str()),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        let a_b = ["A".to_string(), "B".to_string()];
+        assert_eq!(
+            notes,
+            vec![
+                (NotePosition::Across, NoteShape::Folded, &[][..], None, "Phase 1"),
+                (NotePosition::Over, NoteShape::Rectangle, &a_b[..], Some("#yellow"), "boxed"),
+                (NotePosition::Left, NoteShape::Hexagon, &a_b[1..], None, "step"),
+                (NotePosition::Across, NoteShape::Hexagon, &[][..], Some("#c0ffee"), "multi"),
+            ]
+        );
+
+        let output = parse_with_diagnostics("note over A #not-a-color: x\nnote acros: y");
+        assert_eq!(output.diagnostics[0].message, "invalid note color");
+        assert_eq!(output.diagnostics[0].span.column, 13);
+        assert_eq!(
+            output.diagnostics[1].message,
+            "expected `left of`, `right of`, `over` or `across` after `note`"
+        );
+    }
+
+    #[test]
+    fn test_link_note() {
+        let output = parse_with_diagnostics(
+            "note on link: orphan\nA->B: call\nnote on link: schema v2\nNote On Link: cached\nnote on link\nnote over B: separate",
+        );
+        match &output.diagram.items[0] {
+            Item::Message { note, .. } => assert_eq!(note.as_deref(), Some("schema v2\\ncached")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(&output.diagram.items[1], Item::Note { .. }));
+        let messages: Vec<(usize, &str)> = output
+            .diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                (1, "`note on link` must follow a message"),
+                (5, "expected `:` and text after `note on link`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_row() {
+        let output = parse_with_diagnostics(
+            "& A->B: first\nA->B: x & A->C: y\n& A->+D: z\nA->B: Q & A\nnote over A: n\n& A->B: late",
+        );
+        let items = &output.diagram.items;
+        assert_eq!(items.len(), 3);
+        match &items[0] {
+            Item::Block {
+                kind: BlockKind::Row,
+                items: row,
+                span,
+                ..
+            } => {
+                let targets: Vec<(&str, &str, usize)> = row
+                    .iter()
+                    .map(|item| match item {
+                        Item::Message { to, text, span, .. } => (to.as_str(), text.as_str(), span.column),
+                        other => panic!("unexpected {:?}", other),
+                    })
+                    .collect();
+                assert_eq!(targets, vec![("B", "x", 1), ("C", "y", 11), ("D", "z", 3)]);
+                assert_eq!((span.line, span.end_line), (2, 3));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(&items[1], Item::Message { text, .. } if text == "Q & A"));
+        // Only a `&` with space around it separates messages
+        let result = parse("A->B: R&D->QA handoff\nA->B: x &A->C: y").unwrap();
+        assert!(matches!(&result.items[0], Item::Message { text, .. } if text == "R&D->QA handoff"));
+        assert!(matches!(&result.items[1], Item::Message { text, .. } if text == "x &A->C: y"));
+
+        let messages: Vec<(usize, &str)> = output
+            .diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![(1, "`&` must follow a message"), (6, "`&` must follow a message")]
+        );
+    }
+
     #[test]
     fn test_note() {
         let result = parse("note over Alice: Hello").unwrap();
@@ -1010,6 +2744,7 @@ mod tests {
                 position,
                 participants,
                 text,
+                ..
             } => {
                 assert_eq!(*position, NotePosition::Over);
                 assert_eq!(participants, &["Alice"]);
@@ -1035,6 +2770,132 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_uml_fragments() {
+        let kinds = [
+            ("break", BlockKind::Break),
+            ("critical", BlockKind::Critical),
+            ("neg", BlockKind::Neg),
+            ("assert", BlockKind::Assert),
+            ("strict", BlockKind::Strict),
+            ("region", BlockKind::Region),
+        ];
+        for (keyword, expected) in kinds {
+            let result = parse(&format!("{} guard\nAlice->Bob: Hello\nend", keyword)).unwrap();
+            match &result.items[0] {
+                Item::Block { kind, label, .. } => {
+                    assert_eq!(*kind, expected);
+                    assert_eq!(label, "guard");
+                }
+                _ => panic!("Expected Block for {}", keyword),
+            }
+        }
+
+        let result = parse("ignore {ping, pong} retries\nAlice->Bob: Hello\nend\nconsider{Hello}\nend").unwrap();
+        match &result.items[..] {
+            [Item::Block {
+                kind: BlockKind::Ignore,
+                label,
+                messages,
+                ..
+            }, Item::Block {
+                kind: BlockKind::Consider,
+                messages: considered,
+                ..
+            }] => {
+                assert_eq!(label, "retries");
+                assert_eq!(messages, &["ping", "pong"]);
+                assert_eq!(considered, &["Hello"]);
+            }
+            other => panic!("Expected ignore and consider blocks, got {:?}", other),
+        }
+
+        // Participants named like a fragment keyword still send messages
+        let result = parse("Region -> Bob: Hello").unwrap();
+        assert!(matches!(&result.items[0], Item::Message { from, .. } if from == "Region"));
+        let result = parse("Critical Path->X: a\nBreak Room->X: a\nRegion Server->X: a\nignore Cache->X: a").unwrap();
+        let senders: Vec<&str> = result
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Message { from, .. } => from.as_str(),
+                other => panic!("Expected Message, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(senders, vec!["Critical Path", "Break Room", "Region Server", "ignore Cache"]);
+    }
+
+    #[test]
+    fn test_group_block() {
+        let result = parse("group Retry policy [max 3]\ngroup TX boundary\nAlice->Bob: Hello\nend\nend").unwrap();
+        match &result.items[0] {
+            Item::Block {
+                kind,
+                label,
+                condition,
+                items,
+                ..
+            } => {
+                assert_eq!(*kind, BlockKind::Group);
+                assert_eq!(label, "Retry policy");
+                assert_eq!(condition.as_deref(), Some("max 3"));
+                assert!(matches!(&items[0], Item::Block { label, condition: None, .. } if label == "TX boundary"));
+            }
+            _ => panic!("Expected Block"),
+        }
+
+        let result = parse("Group Manager->Bob: hi").unwrap();
+        assert!(matches!(&result.items[0], Item::Message { from, .. } if from == "Group Manager"));
+    }
+
+    #[test]
+    fn test_participant_box() {
+        let input = "box \"Backend services\" #lightblue\nparticipant API\nparticipant Database as DB\nend box\nbox\nparticipant Cache\nend box\nAPI->DB: query";
+        let result = parse(input).unwrap();
+        match &result.items[2] {
+            Item::ParticipantBox {
+                title,
+                color,
+                participants,
+                span,
+            } => {
+                assert_eq!(title.as_deref(), Some("Backend services"));
+                assert_eq!(color.as_deref(), Some("#lightblue"));
+                assert_eq!(participants, &["API", "DB"]);
+                assert_eq!((span.line, span.end_line), (1, 4));
+            }
+            other => panic!("Expected ParticipantBox, got {:?}", other),
+        }
+        assert!(matches!(&result.items[4], Item::ParticipantBox { title: None, color: None, .. }));
+
+        // `Box -> Bob` is still a message
+        assert!(matches!(&parse("Box -> Bob: hi").unwrap().items[0], Item::Message { .. }));
+    }
+
+    #[test]
+    fn test_participant_box_errors() {
+        let output = parse_with_diagnostics("box A\nparticipant X\nbox B\nend box\nend box\nbox C\nparticipant Y");
+        let messages: Vec<_> = output
+            .diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.is_error()))
+            .collect();
+        // Nested box, orphan `end box`, unclosed box
+        assert_eq!(messages, vec![(3, true), (5, false), (6, false)]);
+        let boxes = output
+            .diagram
+            .items
+            .iter()
+            .filter(|item| matches!(item, Item::ParticipantBox { .. }))
+            .count();
+        assert_eq!(boxes, 2);
+
+        // A participant named like the keyword sends a message
+        let output = parse_with_diagnostics("Box Office->Alice: ticket");
+        assert!(output.diagnostics.is_empty());
+        assert!(matches!(&output.diagram.items[0], Item::Message { from, .. } if from == "Box Office"));
+    }
+
     #[test]
     fn test_alt_else_block() {
         let result =
@@ -1087,6 +2948,7 @@ end note"#;
                 position,
                 participants,
                 text,
+                ..
             } => {
                 assert_eq!(*position, NotePosition::Left);
                 assert_eq!(participants, &["Alice"]);
@@ -1102,7 +2964,9 @@ end note"#;
         let result = parse("state over Server: LISTEN").unwrap();
         assert_eq!(result.items.len(), 1);
         match &result.items[0] {
-            Item::State { participants, text } => {
+            Item::State {
+                participants, text, ..
+            } => {
                 assert_eq!(participants, &["Server"]);
                 assert_eq!(text, "LISTEN");
             }
@@ -1142,6 +3006,7 @@ end ref-->Alice: Output signal"#;
                 input_label,
                 output_to,
                 output_label,
+                ..
             } => {
                 assert_eq!(participants, &["Bob", "Carol"]);
                 assert_eq!(text, "line 1\\nline 2");
@@ -1160,7 +3025,7 @@ end ref-->Alice: Output signal"#;
         let result = parse("option footer=none").unwrap();
         assert_eq!(result.items.len(), 1);
         match &result.items[0] {
-            Item::DiagramOption { key, value } => {
+            Item::DiagramOption { key, value, .. } => {
                 assert_eq!(key, "footer");
                 assert_eq!(value, "none");
             }
@@ -1195,7 +3060,9 @@ OSD Backend-->OSD Frontend: Response"#;
 
         // Check participant declarations
         match &result.items[0] {
-            Item::ParticipantDecl { name, alias, kind } => {
+            Item::ParticipantDecl {
+                name, alias, kind, ..
+            } => {
                 assert_eq!(name, "OSD Frontend");
                 assert_eq!(*alias, None);
                 assert_eq!(*kind, ParticipantKind::Participant);
@@ -1203,7 +3070,9 @@ OSD Backend-->OSD Frontend: Response"#;
             _ => panic!("Expected ParticipantDecl"),
         }
         match &result.items[1] {
-            Item::ParticipantDecl { name, alias, kind } => {
+            Item::ParticipantDecl {
+                name, alias, kind, ..
+            } => {
                 assert_eq!(name, "OSD Backend");
                 assert_eq!(*alias, None);
                 assert_eq!(*kind, ParticipantKind::Participant);
@@ -1244,4 +3113,193 @@ OSD Backend-->OSD Frontend: Response"#;
             _ => panic!("Expected ParticipantDecl"),
         }
     }
+
+    #[test]
+    fn test_item_spans() {
+        let input = "title T\r\n  Alice->Bob: Hi\r\nnote over Bob\r\nline\r\nend note";
+        let result = parse(input).unwrap();
+        assert_eq!(result.items.len(), 2);
+
+        let span = result.items[0].span();
+        assert_eq!(&input[span.start..span.end], "Alice->Bob: Hi");
+        assert_eq!((span.line, span.column), (2, 3));
+        assert_eq!((span.end_line, span.end_column), (2, 17));
+
+        let span = result.items[1].span();
+        assert_eq!((span.line, span.end_line), (3, 5));
+        assert!(input[span.start..span.end].ends_with("end note"));
+    }
+
+    #[test]
+    fn test_block_spans() {
+        let input = "alt ok\nA->B: x\nelse fail\nA->B: y\nend\nA->B: z";
+        let result = parse(input).unwrap();
+        match &result.items[0] {
+            Item::Block {
+                else_sections, span, ..
+            } => {
+                assert_eq!((span.line, span.end_line), (1, 5));
+                assert_eq!(&input[span.start..span.end], &input[..input.find("\nA->B: z").unwrap()]);
+                assert_eq!((else_sections[0].span.line, else_sections[0].span.end_line), (3, 4));
+            }
+            _ => panic!("Expected Block"),
+        }
+        let offset = input.find("A->B: y").unwrap();
+        match Item::find_at(&result.items, offset) {
+            Some(Item::Message { text, .. }) => assert_eq!(text, "y"),
+            other => panic!("Expected Message, got {:?}", other),
+        }
+        let participants = result.participants();
+        assert_eq!(participants[1].span.line, 2);
+    }
+
+    #[test]
+    fn test_diagnostics_report_every_error() {
+        let input = "Alice->Bob: ok\npartcipant Carol\nAlice->\"Bob\" hello\nBob->Alice: fine\n->Bob: x";
+        let output = parse_with_diagnostics(input);
+        assert_eq!(output.diagram.items.len(), 2);
+        assert_eq!(output.diagnostics.len(), 3);
+
+        let typo = &output.diagnostics[0];
+        assert_eq!(typo.span.line, 2);
+        assert_eq!((typo.span.column, typo.span.end_column), (1, 11));
+        assert_eq!(
+            typo.suggestion.as_ref().and_then(|s| s.replacement.as_deref()),
+            Some("participant")
+        );
+
+        let colon = &output.diagnostics[1];
+        assert_eq!(colon.message, "expected `:` after target participant");
+        assert_eq!(colon.span.line, 3);
+        assert_eq!(
+            colon.suggestion.as_ref().and_then(|s| s.replacement.as_deref()),
+            Some(": hello")
+        );
+
+        assert_eq!(output.diagnostics[2].span.line, 5);
+    }
+
+    #[test]
+    fn test_parse_error_is_human_readable() {
+        let err = parse("Alice->Bob: ok\nactivate").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Parse error at line 2: expected a participant name after `activate`"
+        );
+    }
+
+    #[test]
+    fn test_strict_unclosed_block() {
+        let input = "Alice->Bob: a\nloop forever\nalt ok\nAlice->Bob: b\nend";
+        let err = parse_strict(input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Parse error at line 2: `loop` block is never closed with `end`"
+        );
+
+        // Lenient parsing keeps the content and only warns
+        let output = parse_with_diagnostics(input);
+        assert!(!output.has_errors());
+        assert_eq!(output.diagnostics.len(), 1);
+        assert_eq!(output.diagram.items.len(), 2);
+        match &output.diagram.items[1] {
+            Item::Block { kind, items, span, .. } => {
+                assert_eq!(*kind, BlockKind::Loop);
+                assert_eq!(items.len(), 1);
+                assert_eq!((span.line, span.end_line), (2, 5));
+            }
+            _ => panic!("Expected Block"),
+        }
+    }
+
+    #[test]
+    fn test_strict_orphan_else_and_end() {
+        let output = parse_with_options("else\nAlice->Bob: a\nend", ParseOptions::strict());
+        let messages: Vec<_> = output
+            .diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            vec![(1, "`else` outside of a block"), (3, "`end` without a matching block")]
+        );
+        assert_eq!(output.diagram.items.len(), 1);
+    }
+
+    #[test]
+    fn test_strict_unclosed_note_and_ref() {
+        let err = parse_strict("note over Alice\ntext\nAlice->Bob: hi").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Parse error at line 1: note is never closed with `end note`"
+        );
+        let err = parse_strict("A->B: x\nref over A, B\ntext").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Parse error at line 2: ref is never closed with `end ref`"
+        );
+        assert!(parse("note over Alice\ntext").is_ok());
+    }
+
+    #[test]
+    fn test_nested_brace_blocks() {
+        let input = r#"parallel {
+    note over Alice
+    multi
+    end note
+    serial {
+        alt ok
+            Alice->Bob: a
+        else
+            Alice->Bob: b
+        end
+        parallel {
+            ref over Alice, Bob
+            details
+            end ref
+        }
+    }
+}
+Bob->Alice: done"#;
+        let output = parse_with_diagnostics(input);
+        assert!(output.diagnostics.is_empty(), "{:?}", output.diagnostics);
+        let items = &output.diagram.items;
+        assert_eq!(items.len(), 2);
+
+        let Item::Block { kind, items: outer, span, .. } = &items[0] else {
+            panic!("Expected parallel block");
+        };
+        assert_eq!(*kind, BlockKind::Parallel);
+        assert_eq!((span.line, span.end_line), (1, 17));
+        assert!(matches!(&outer[0], Item::Note { text, .. } if text == "multi"));
+
+        let Item::Block { kind, items: serial, .. } = &outer[1] else {
+            panic!("Expected serial block");
+        };
+        assert_eq!(*kind, BlockKind::Serial);
+        assert!(matches!(&serial[0], Item::Block { kind: BlockKind::Alt, else_sections, .. } if else_sections.len() == 1));
+        let Item::Block { kind, items: inner, .. } = &serial[1] else {
+            panic!("Expected nested parallel block");
+        };
+        assert_eq!(*kind, BlockKind::Parallel);
+        assert!(matches!(&inner[0], Item::Ref { text, .. } if text == "details"));
+    }
+
+    #[test]
+    fn test_brace_block_errors() {
+        let input = "parallel {\n  Alice->Bob: ok\n  activate\n  serial {\n    loop forever\n  }\n}\n}";
+        let output = parse_with_diagnostics(input);
+        let messages: Vec<_> = output
+            .diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.is_error()))
+            .collect();
+        // Bad statement, unclosed loop inside the brace body, stray `}`
+        assert_eq!(messages, vec![(3, true), (5, false), (8, true)]);
+        let Item::Block { items, .. } = &output.diagram.items[0] else {
+            panic!("Expected parallel block");
+        };
+        assert_eq!(items.len(), 2);
+    }
 }
@@ -0,0 +1,786 @@
+//! Source preprocessing ahead of parsing
+//!
+//! Directives are expanded before the diagram is parsed:
+//!
+//! - `!include path` inserts the text of another file, as returned by an
+//!   [`IncludeResolver`]
+//! - `!define NAME value` replaces the word `NAME` in later lines (`!undef NAME`
+//!   removes it); a define without a value is a flag
+//! - `!procedure Name($arg, ...)` ... `!endprocedure` defines a macro, expanded
+//!   where a line reads `Name(value, ...)`
+//! - `!if FLAG` / `!if !FLAG`, `!else` and `!endif` keep lines depending on
+//!   whether a flag is defined
+//!
+//! The expanded text is parsed as usual and a line map translates spans back
+//! to the main input, so problems in an included file or a macro expansion
+//! are reported at the line that produced them, with the include chain.
+//!
+//! # Example
+//!
+//! ```
+//! use osd_core::parse_with_resolver;
+//! use osd_core::preprocess::MemoryResolver;
+//!
+//! let resolver = MemoryResolver::new().with_file("team.osd", "participant Alice as A\nparticipant Bob as B");
+//! let diagram = parse_with_resolver("!include team.osd\nA->B: Hello", &resolver).unwrap();
+//! assert_eq!(diagram.participants()[0].name, "Alice");
+//! ```
+
+use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Item, Span};
+use crate::diagnostic::Diagnostic;
+use crate::parser::SourceLine;
+
+/// A file resolved for an `!include`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludedSource {
+    /// Canonical name, used for cycle detection and in messages
+    pub name: String,
+    /// Source text
+    pub text: String,
+}
+
+/// Maps `!include` paths to source text
+pub trait IncludeResolver {
+    /// Resolve `path` as written in the file named `from` (`None` for the main input)
+    fn resolve(&self, path: &str, from: Option<&str>) -> Result<IncludedSource, String>;
+}
+
+/// Resolves includes from the file system, relative to the including file
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct FileResolver {
+    base: PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileResolver {
+    /// Resolve includes in the main input relative to `base`
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self { base: base.into() }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl IncludeResolver for FileResolver {
+    fn resolve(&self, path: &str, from: Option<&str>) -> Result<IncludedSource, String> {
+        let dir = match from {
+            Some(from) => Path::new(from).parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => self.base.clone(),
+        };
+        let full = dir.join(path);
+        let text = std::fs::read_to_string(&full).map_err(|e| e.to_string())?;
+        let name = full.canonicalize().unwrap_or(full).display().to_string();
+        Ok(IncludedSource { name, text })
+    }
+}
+
+/// Resolves includes from an in-memory map of path to source text
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResolver {
+    files: HashMap<String, String>,
+}
+
+impl MemoryResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file
+    pub fn with_file(mut self, path: impl Into<String>, text: impl Into<String>) -> Self {
+        self.insert(path, text);
+        self
+    }
+
+    /// Add or replace a file
+    pub fn insert(&mut self, path: impl Into<String>, text: impl Into<String>) {
+        self.files.insert(path.into(), text.into());
+    }
+}
+
+impl IncludeResolver for MemoryResolver {
+    fn resolve(&self, path: &str, _from: Option<&str>) -> Result<IncludedSource, String> {
+        self.files
+            .get(path)
+            .map(|text| IncludedSource {
+                name: path.to_string(),
+                text: text.clone(),
+            })
+            .ok_or_else(|| "file not found".to_string())
+    }
+}
+
+/// Where a line of the expanded text came from
+#[derive(Debug, Clone)]
+struct LineOrigin {
+    /// 0-based line in the main input (the `!include` line for included text)
+    root: usize,
+    /// The line is a main input line, unchanged, so columns carry over
+    exact: bool,
+    /// File name and 1-based line of each step from the main input inwards
+    chain: Vec<(String, usize)>,
+}
+
+/// Expanded source text with a map back to the main input
+pub(crate) struct Expansion<'a> {
+    /// Text to parse
+    pub(crate) text: String,
+    /// Problems found while expanding, with spans in the main input
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    root_lines: Vec<SourceLine<'a>>,
+    origins: Vec<LineOrigin>,
+    /// Offset of each expanded line in `text`
+    offsets: Vec<usize>,
+}
+
+impl Expansion<'_> {
+    /// Whether the expanded text is the main input unchanged
+    fn is_identity(&self) -> bool {
+        self.origins.len() == self.root_lines.len() && self.origins.iter().all(|o| o.exact)
+    }
+
+    /// Translate parser diagnostics and item spans back to the main input
+    pub(crate) fn remap(&self, items: &mut [Item], diagnostics: &mut [Diagnostic]) {
+        if self.is_identity() {
+            return;
+        }
+        for diagnostic in diagnostics.iter_mut() {
+            if let Some(related) = &mut diagnostic.related {
+                related.span = self.map_span(related.span);
+            }
+            if let Some(origin) = self.origin(diagnostic.span.line) {
+                if !origin.chain.is_empty() {
+                    diagnostic.message = format!("{} ({})", diagnostic.message, describe_chain(&origin.chain));
+                }
+            }
+            diagnostic.span = self.map_span(diagnostic.span);
+        }
+        self.remap_items(items);
+    }
+
+    fn remap_items(&self, items: &mut [Item]) {
+        for item in items {
+            item.set_span(self.map_span(item.span()));
+            if let Item::Block {
+                items, else_sections, ..
+            } = item
+            {
+                self.remap_items(items);
+                for section in else_sections {
+                    section.span = self.map_span(section.span);
+                    self.remap_items(&mut section.items);
+                }
+            }
+        }
+    }
+
+    fn origin(&self, line: usize) -> Option<&LineOrigin> {
+        line.checked_sub(1).and_then(|index| self.origins.get(index))
+    }
+
+    fn map_span(&self, span: Span) -> Span {
+        let (start, line, column) = self.map_point(span.line, span.start, span.column, false);
+        let (end, end_line, end_column) = self.map_point(span.end_line, span.end, span.end_column, true);
+        Span {
+            start,
+            end,
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+
+    /// Map a position on an expanded line; inexact lines map to the whole main input line
+    fn map_point(&self, line: usize, offset: usize, column: usize, is_end: bool) -> (usize, usize, usize) {
+        let Some(origin) = self.origin(line) else {
+            return (offset, line, column);
+        };
+        let root = &self.root_lines[origin.root];
+        if origin.exact {
+            let delta = offset.saturating_sub(self.offsets[line - 1]);
+            return (root.offset + delta, root.number, column);
+        }
+        let whole = root.span();
+        if is_end {
+            (whole.end, whole.end_line, whole.end_column)
+        } else {
+            (whole.start, whole.line, whole.column)
+        }
+    }
+}
+
+/// "in `c.osd` line 3, included from `b.osd` line 5"
+fn describe_chain(chain: &[(String, usize)]) -> String {
+    let mut steps = chain.iter().rev();
+    let mut text = match steps.next() {
+        Some((name, line)) => format!("in `{}` line {}", name, line),
+        None => return String::new(),
+    };
+    for (name, line) in steps {
+        text.push_str(&format!(", included from `{}` line {}", name, line));
+    }
+    text
+}
+
+/// Deepest nesting of procedure calls, to stop runaway recursion
+const MAX_CALL_DEPTH: usize = 32;
+
+/// Most procedure calls expanded for one input, to stop runaway fan-out
+/// (a procedure calling itself twice would otherwise expand 2^32 times)
+const MAX_CALLS: usize = 10_000;
+
+/// A `!procedure` definition
+#[derive(Debug, Clone, Default)]
+struct Procedure {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+/// A `!procedure` whose body is being collected
+struct Recording {
+    name: String,
+    procedure: Procedure,
+    span: Span,
+    context: String,
+}
+
+/// An open `!if`
+struct Condition {
+    /// Every enclosing branch is kept
+    parent_active: bool,
+    /// The condition held
+    taken: bool,
+    /// After the `!else`
+    in_else: bool,
+    span: Span,
+    context: String,
+}
+
+/// Split `!name rest` into the directive name and its trimmed argument
+fn directive(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix('!')?;
+    let end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+    if end == 0 {
+        return None;
+    }
+    Some((&rest[..end], rest[end..].trim()))
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Replace every whole word for which `lookup` returns a value
+fn replace_words<'v>(text: &str, lookup: impl Fn(&str) -> Option<&'v str>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_word_char) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !is_word_char(c)).unwrap_or(rest.len());
+        let word = &rest[..end];
+        result.push_str(lookup(word).unwrap_or(word));
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Split `Name(a, "b, c")` into the name and its arguments
+fn parse_call(line: &str) -> Option<(&str, Vec<String>)> {
+    let inner = line.strip_suffix(')')?;
+    let open = inner.find('(')?;
+    let name = inner[..open].trim();
+    if !is_identifier(name) {
+        return None;
+    }
+    let inner = inner[open + 1..].trim();
+    if inner.is_empty() {
+        return Some((name, Vec::new()));
+    }
+
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in inner.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => args.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    args.push(current.trim().to_string());
+    Some((name, args))
+}
+
+/// Parse the `Name($a, $b)` of a `!procedure`
+fn parse_signature(text: &str) -> Option<(&str, Vec<String>)> {
+    let (name, params) = parse_call(text)?;
+    params
+        .iter()
+        .all(|p| p.strip_prefix('$').is_some_and(is_identifier))
+        .then_some((name, params))
+}
+
+/// Path of an `!include` directive
+fn include_path(rest: &str) -> &str {
+    rest.strip_prefix('"')
+        .and_then(|p| p.strip_suffix('"'))
+        .unwrap_or(rest)
+}
+
+/// Run the preprocessor: `!include`, `!define`/`!undef`, `!procedure` macros
+/// and `!if`/`!else`/`!endif`; without a resolver every include is an error
+pub(crate) fn expand<'a>(input: &'a str, resolver: Option<&dyn IncludeResolver>) -> Expansion<'a> {
+    let root_lines = SourceLine::split(input);
+    let mut expander = Expander {
+        resolver,
+        root_lines: &root_lines,
+        lines: Vec::new(),
+        origins: Vec::new(),
+        diagnostics: Vec::new(),
+        stack: Vec::new(),
+        defines: HashMap::new(),
+        procedures: HashMap::new(),
+        conditions: Vec::new(),
+        recording: None,
+        depth: 0,
+        calls: 0,
+        halted: false,
+    };
+    for (index, line) in root_lines.iter().enumerate() {
+        expander.line(line.text, index, &[], true);
+    }
+    expander.finish(0);
+
+    let Expander {
+        lines,
+        origins,
+        diagnostics,
+        ..
+    } = expander;
+    let mut offsets = Vec::with_capacity(lines.len());
+    let mut offset = 0;
+    for line in &lines {
+        offsets.push(offset);
+        offset += line.len() + 1;
+    }
+    let mut expansion = Expansion {
+        text: lines.join("\n"),
+        diagnostics,
+        root_lines,
+        origins,
+        offsets,
+    };
+    if expansion.is_identity() {
+        // Keep the original text so offsets stay valid for `\r\n` input
+        expansion.text = input.to_string();
+    }
+    expansion
+}
+
+struct Expander<'r, 'a> {
+    resolver: Option<&'r dyn IncludeResolver>,
+    root_lines: &'r [SourceLine<'a>],
+    lines: Vec<String>,
+    origins: Vec<LineOrigin>,
+    diagnostics: Vec<Diagnostic>,
+    /// Names of the files currently being expanded, outermost first
+    stack: Vec<String>,
+    defines: HashMap<String, String>,
+    procedures: HashMap<String, Procedure>,
+    conditions: Vec<Condition>,
+    recording: Option<Recording>,
+    /// Nesting of procedure calls
+    depth: usize,
+    /// Procedure calls expanded so far
+    calls: usize,
+    /// Set once a call limit is hit, so runaway expansion stops with one diagnostic
+    halted: bool,
+}
+
+impl Expander<'_, '_> {
+    /// Whether lines are currently kept (every open `!if` branch holds)
+    fn active(&self) -> bool {
+        self.conditions
+            .last()
+            .map_or(true, |c| c.parent_active && c.taken != c.in_else)
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic::error(span, message));
+    }
+
+    /// Report `!if`s and a `!procedure` left open at the end of a file or procedure body
+    fn finish(&mut self, depth: usize) {
+        for condition in self.conditions.split_off(depth) {
+            self.error(
+                condition.span,
+                format!("`!if` is never closed with `!endif`{}", condition.context),
+            );
+        }
+        if let Some(recording) = self.recording.take() {
+            self.error(
+                recording.span,
+                format!(
+                    "`!procedure {}` is never closed with `!endprocedure`{}",
+                    recording.name, recording.context
+                ),
+            );
+        }
+    }
+
+    /// Expand one line; `chain` locates it in included files and `exact` marks
+    /// a main input line whose columns are still valid
+    fn line(&mut self, text: &str, root: usize, chain: &[(String, usize)], exact: bool) {
+        let span = self.root_lines[root].span();
+        let context = if chain.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", describe_chain(chain))
+        };
+        let directive = directive(text.trim());
+
+        if let Some(recording) = &mut self.recording {
+            if matches!(directive, Some(("endprocedure", _))) {
+                let recording = self.recording.take().unwrap();
+                self.procedures.insert(recording.name, recording.procedure);
+            } else {
+                recording.procedure.body.push(text.to_string());
+            }
+            return;
+        }
+
+        match directive {
+            Some(("if", flag)) => {
+                let (negated, name) = match flag.strip_prefix('!') {
+                    Some(name) => (true, name.trim()),
+                    None => (false, flag),
+                };
+                if !is_identifier(name) {
+                    self.error(span, format!("expected `!if FLAG` or `!if !FLAG`{}", context));
+                }
+                self.conditions.push(Condition {
+                    parent_active: self.active(),
+                    taken: self.defines.contains_key(name) != negated,
+                    in_else: false,
+                    span,
+                    context,
+                });
+                return;
+            }
+            Some(("else", _)) => {
+                match self.conditions.last_mut() {
+                    Some(condition) if !condition.in_else => condition.in_else = true,
+                    Some(_) => self.error(span, format!("`!if` already has an `!else`{}", context)),
+                    None => self.error(span, format!("`!else` without `!if`{}", context)),
+                }
+                return;
+            }
+            Some(("endif", _)) => {
+                if self.conditions.pop().is_none() {
+                    self.error(span, format!("`!endif` without `!if`{}", context));
+                }
+                return;
+            }
+            _ => {}
+        }
+        if !self.active() {
+            return;
+        }
+
+        match directive {
+            Some(("define", rest)) => {
+                let (name, value) = rest
+                    .split_once(char::is_whitespace)
+                    .map_or((rest, ""), |(name, value)| (name, value.trim()));
+                if is_identifier(name) {
+                    let value = replace_words(value, |word| self.defines.get(word).map(String::as_str));
+                    self.defines.insert(name.to_string(), value);
+                } else {
+                    self.error(span, format!("expected `!define NAME value`{}", context));
+                }
+            }
+            Some(("undef", name)) => {
+                self.defines.remove(name);
+            }
+            Some(("procedure", signature)) => match parse_signature(signature) {
+                Some((name, params)) => {
+                    self.recording = Some(Recording {
+                        name: name.to_string(),
+                        procedure: Procedure {
+                            params,
+                            body: Vec::new(),
+                        },
+                        span,
+                        context,
+                    });
+                }
+                None => self.error(span, format!("expected `!procedure Name($arg, ...)`{}", context)),
+            },
+            Some(("endprocedure", _)) => {
+                self.error(span, format!("`!endprocedure` without `!procedure`{}", context));
+            }
+            Some(("include", rest)) => self.include(include_path(rest), root, chain, span, &context),
+            // Anything else is diagram text, such as `!important` in a note body;
+            // the parser reports it if it turns out to be a stray directive
+            Some(_) | None => {
+                let substituted = replace_words(text, |word| self.defines.get(word).map(String::as_str));
+                if let Some((name, args)) = parse_call(substituted.trim()) {
+                    if self.procedures.contains_key(name) {
+                        let name = name.to_string();
+                        self.call(&name, args, root, chain, span, &context);
+                        return;
+                    }
+                }
+                self.origins.push(LineOrigin {
+                    root,
+                    exact: exact && substituted == text,
+                    chain: chain.to_vec(),
+                });
+                self.lines.push(substituted);
+            }
+        }
+    }
+
+    fn include(&mut self, path: &str, root: usize, chain: &[(String, usize)], span: Span, context: &str) {
+        let Some(resolver) = self.resolver else {
+            self.diagnostics.push(
+                Diagnostic::error(span, format!("cannot include `{}`: includes are not available{}", path, context))
+                    .with_suggestion("parse with `parse_with_resolver`", None),
+            );
+            return;
+        };
+        let from = chain.last().map(|(name, _)| name.as_str());
+        let source = match resolver.resolve(path, from) {
+            Ok(source) => source,
+            Err(message) => {
+                self.error(span, format!("cannot include `{}`: {}{}", path, message, context));
+                return;
+            }
+        };
+        if self.stack.contains(&source.name) {
+            let cycle: Vec<&str> = self
+                .stack
+                .iter()
+                .map(String::as_str)
+                .chain(std::iter::once(source.name.as_str()))
+                .collect();
+            let message = format!("include cycle: {}", cycle.join(" -> "));
+            self.error(span, message);
+            return;
+        }
+
+        self.stack.push(source.name.clone());
+        let depth = self.conditions.len();
+        for included in SourceLine::split(&source.text) {
+            let mut here = chain.to_vec();
+            here.push((source.name.clone(), included.number));
+            self.line(included.text, root, &here, false);
+        }
+        self.finish(depth);
+        self.stack.pop();
+    }
+
+    fn call(&mut self, name: &str, args: Vec<String>, root: usize, chain: &[(String, usize)], span: Span, context: &str) {
+        let procedure = self.procedures[name].clone();
+        if args.len() != procedure.params.len() {
+            self.error(
+                span,
+                format!(
+                    "procedure `{}` takes {} argument(s) but {} were given{}",
+                    name,
+                    procedure.params.len(),
+                    args.len(),
+                    context
+                ),
+            );
+            return;
+        }
+        if self.halted {
+            return;
+        }
+        if self.calls == MAX_CALLS {
+            self.error(
+                span,
+                format!("procedure `{}` expands too many calls (more than {}){}", name, MAX_CALLS, context),
+            );
+            self.halted = true;
+            return;
+        }
+        if self.depth == MAX_CALL_DEPTH {
+            self.error(span, format!("procedure `{}` is nested too deeply{}", name, context));
+            self.halted = true;
+            return;
+        }
+
+        self.calls += 1;
+        self.depth += 1;
+        let depth = self.conditions.len();
+        for line in &procedure.body {
+            let line = replace_words(line, |word| {
+                let index = procedure.params.iter().position(|p| p == word)?;
+                Some(args[index].as_str())
+            });
+            self.line(&line, root, chain, false);
+        }
+        self.finish(depth);
+        self.depth -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, parse_with_resolver, ParseError};
+
+    fn resolver() -> MemoryResolver {
+        MemoryResolver::new()
+            .with_file("team.osd", "participant Alice as A\nparticipant Bob as B")
+            .with_file("calls.osd", "A->B: first\n!include inner.osd")
+            .with_file("inner.osd", "B-->A: second\n!!! broken")
+            .with_file("loop.osd", "A->B: again\n!include loop.osd")
+    }
+
+    #[test]
+    fn test_include() {
+        let input = "!include team.osd\n  !include \"calls.osd\"\nA->B: last";
+        let resolver = resolver().with_file("inner.osd", "B-->A: second");
+        let diagram = parse_with_resolver(input, &resolver).unwrap();
+        assert_eq!(diagram.participants()[1].name, "Bob");
+        assert_eq!(diagram.items.len(), 5);
+
+        // Included items point at the `!include` line, main input items keep their columns
+        assert_eq!(diagram.items[0].span().line, 1);
+        let nested = diagram.items[3].span();
+        assert_eq!((nested.line, nested.column, nested.start), (2, 3, 20));
+        let last = diagram.items[4].span();
+        assert_eq!((last.line, last.column, last.start), (3, 1, 41));
+        assert_eq!(&input[last.start..last.end], "A->B: last");
+    }
+
+    fn error(input: &str, resolver: &MemoryResolver) -> (usize, String) {
+        match parse_with_resolver(input, resolver).unwrap_err() {
+            ParseError::SyntaxError { line, message } => (line, message),
+        }
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let (line, message) = error("A->B: hi\n!include calls.osd", &resolver());
+        assert_eq!(line, 2);
+        assert!(message.ends_with("(in `inner.osd` line 2, included from `calls.osd` line 2)"));
+
+        let (line, message) = error("!include team.osd\n!include loop.osd", &resolver());
+        assert_eq!((line, message.as_str()), (2, "include cycle: loop.osd -> loop.osd"));
+
+        let (line, message) = error("\n!include missing.osd", &resolver());
+        assert_eq!((line, message.as_str()), (2, "cannot include `missing.osd`: file not found"));
+
+        let error = parse("!include team.osd").unwrap_err();
+        assert!(error.to_string().contains("includes are not available"));
+    }
+
+    #[test]
+    fn test_define_and_conditionals() {
+        let input = "!define API v2\n!define CACHE\n!if CACHE\nA->Cache: get API\n!if !API\nA->B: hidden\n!endif\n!else\nA->DB: query\n!endif\nA->B: API";
+        let diagram = parse(input).unwrap();
+        let texts: Vec<(String, usize)> = diagram
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Message { to, text, span, .. } => (format!("{}: {}", to, text), span.line),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(texts, vec![("Cache: get v2".to_string(), 4), ("B: v2".to_string(), 11)]);
+
+        // Line numbers refer to the input, not the expanded text
+        let output = crate::parser::parse_with_diagnostics("!define X\n!if X\nalt a\nA->B: in\n!endif\nA->: bad");
+        let lines: Vec<(usize, &str)> = output
+            .diagnostics
+            .iter()
+            .map(|d| (d.span.line, d.message.as_str()))
+            .collect();
+        assert_eq!(lines[0].0, 3);
+        assert_eq!(lines[1].0, 6);
+
+        // So do related locations
+        let output = crate::parser::parse_with_diagnostics("!define X\nbox A\n!if X\nbox B\n!endif\nend box");
+        let nested = &output.diagnostics[0];
+        assert_eq!(nested.span.line, 4);
+        assert_eq!(nested.related.as_ref().map(|r| r.span.line), Some(2));
+        assert_eq!(nested.full_message(), "`box` cannot be nested (outer box opened at line 2)");
+    }
+
+    #[test]
+    fn test_procedure() {
+        let input = "!procedure Auth($user, $role)\n$user->Server: login as $role\nServer-->$user: token\n!endprocedure\nAuth(Alice, \"admin, ops\")\nAuth(Bob, guest)";
+        let diagram = parse(input).unwrap();
+        assert_eq!(diagram.items.len(), 4);
+        match &diagram.items[2] {
+            Item::Message { from, text, span, .. } => {
+                assert_eq!((from.as_str(), text.as_str(), span.line), ("Bob", "login as guest", 6));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(&diagram.items[0], Item::Message { text, .. } if text == "login as admin, ops"));
+
+        let resolver = MemoryResolver::new();
+        let (line, message) = error("!procedure P($a)\n$a->B: hi\n!endprocedure\nP()", &resolver);
+        assert_eq!((line, message.as_str()), (4, "procedure `P` takes 1 argument(s) but 0 were given"));
+        let (line, message) = error("!procedure P()\nP()\n!endprocedure\nA->B: x\nP()", &resolver);
+        assert_eq!((line, message.as_str()), (5, "procedure `P` is nested too deeply"));
+
+        // Runaway expansion stops at the first limit it hits, with a single diagnostic
+        let output = crate::parser::parse_with_diagnostics("!procedure P()\nA->B: x\nP()\nP()\n!endprocedure\nP()");
+        let messages: Vec<(usize, &str)> = output.diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect();
+        assert_eq!(messages, vec![(6, "procedure `P` is nested too deeply")]);
+        let mut input = String::from("!procedure P0()\nA->B: x\n!endprocedure\n");
+        for level in 1..=14 {
+            input.push_str(&format!("!procedure P{}()\nP{}()\nP{}()\n!endprocedure\n", level, level - 1, level - 1));
+        }
+        input.push_str("P14()");
+        let output = crate::parser::parse_with_diagnostics(&input);
+        let messages: Vec<(usize, &str)> = output.diagnostics.iter().map(|d| (d.span.line, d.message.as_str())).collect();
+        assert_eq!(messages, vec![(60, "procedure `P1` expands too many calls (more than 10000)")]);
+        let (line, message) = error("A->B: x\n!if X\nA->B: y", &resolver);
+        assert_eq!((line, message.as_str()), (2, "`!if` is never closed with `!endif`"));
+        let (line, message) = error("!procedure P()\nA->B: y", &resolver);
+        assert_eq!((line, message.as_str()), (1, "`!procedure P` is never closed with `!endprocedure`"));
+        let (line, message) = error("A->B: x\n!endif", &resolver);
+        assert_eq!((line, message.as_str()), (2, "`!endif` without `!if`"));
+        let (line, message) = error("!pragma teoz true", &resolver);
+        assert_eq!((line, message.as_str()), (1, "unknown directive `!pragma`"));
+        let diagram = parse("note over A\n!important\nend note\nnote over A: !x").unwrap();
+        let notes: Vec<&str> = diagram
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Note { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(notes, vec!["!important", "!x"]);
+    }
+
+    #[test]
+    fn test_file_resolver() {
+        let dir = std::env::temp_dir().join(format!("osd-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("parts")).unwrap();
+        std::fs::write(dir.join("parts/team.osd"), "!include names.osd").unwrap();
+        std::fs::write(dir.join("parts/names.osd"), "participant Alice").unwrap();
+
+        let diagram = parse_with_resolver("!include parts/team.osd", &FileResolver::new(&dir)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(diagram.participants()[0].name, "Alice");
+    }
+}
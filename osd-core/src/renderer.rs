@@ -69,6 +69,18 @@ impl Config {
     }
 }
 
+/// An activation bar on a participant's lifeline
+#[derive(Debug, Clone)]
+struct Activation {
+    start_y: f64,
+    /// `None` while the activation is open (drawn down to the footer)
+    end_y: Option<f64>,
+    /// Number of enclosing activations of the same participant
+    depth: usize,
+    /// Fill overriding the theme's activation fill
+    color: Option<String>,
+}
+
 /// Block background info for deferred rendering
 #[derive(Debug, Clone)]
 struct BlockBackground {
@@ -91,6 +103,121 @@ struct BlockLabel {
     else_sections: Vec<(f64, Option<String>)>,
 }
 
+/// Participant box resolved to participant indices
+#[derive(Debug, Clone)]
+struct ParticipantBoxLayout {
+    title: Option<String>,
+    color: Option<String>,
+    /// Index of the leftmost member participant
+    first: usize,
+    /// Index of the rightmost member participant
+    last: usize,
+}
+
+/// Message numbering state driven by `autonumber` commands
+#[derive(Debug, Clone)]
+struct Autonumbering {
+    /// Next number (one entry per level), or None while numbering is off
+    next: Option<Vec<u32>>,
+    /// Where numbering stopped, for `autonumber resume`
+    stopped: Option<Vec<u32>>,
+    step: u32,
+    format: Option<String>,
+}
+
+impl Default for Autonumbering {
+    fn default() -> Self {
+        Self {
+            next: None,
+            stopped: None,
+            step: 1,
+            format: None,
+        }
+    }
+}
+
+impl Autonumbering {
+    fn apply(&mut self, command: &AutonumberCommand) {
+        match command {
+            AutonumberCommand::Start { start, step, format } => {
+                self.next = Some(start.clone());
+                self.stopped = None;
+                self.step = *step;
+                self.format = format.clone();
+            }
+            AutonumberCommand::Stop => {
+                if let Some(next) = self.next.take() {
+                    self.stopped = Some(next);
+                }
+            }
+            AutonumberCommand::Resume { step, format } => {
+                if self.next.is_none() {
+                    self.next = Some(self.stopped.take().unwrap_or_else(|| vec![1]));
+                }
+                if let Some(step) = step {
+                    self.step = *step;
+                }
+                if format.is_some() {
+                    self.format = format.clone();
+                }
+            }
+            AutonumberCommand::Increment { level } => {
+                let levels = self.next.as_mut().or(self.stopped.as_mut());
+                if let Some(levels) = levels {
+                    if *level < levels.len() {
+                        levels[*level] += 1;
+                        for lower in &mut levels[level + 1..] {
+                            *lower = 1;
+                        }
+                    } else {
+                        levels.resize(level + 1, 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Label for the next message, advancing the counter
+    fn next_label(&mut self) -> Option<String> {
+        let levels = self.next.as_mut()?;
+        let label = format_autonumber(levels, self.format.as_deref());
+        if let Some(last) = levels.last_mut() {
+            *last += self.step;
+        }
+        Some(label)
+    }
+}
+
+/// Format a (possibly hierarchical) number, e.g. `1.2`, or `[002]` with format `[000]`
+///
+/// The first run of `0`s or `#`s in the format is replaced by the number, with
+/// the last level zero-padded to the number of `0`s. A format without such a
+/// run is used as a prefix.
+fn format_autonumber(levels: &[u32], format: Option<&str>) -> String {
+    let Some((last, parents)) = levels.split_last() else {
+        return String::new();
+    };
+    let prefix: String = parents.iter().map(|n| format!("{}.", n)).collect();
+    let Some(format) = format else {
+        return format!("{}{}", prefix, last);
+    };
+    match format.find(['0', '#']) {
+        Some(start) => {
+            let len = format[start..].find(|c| c != '0' && c != '#').unwrap_or(format.len() - start);
+            let width = format[start..start + len].matches('0').count();
+            format!(
+                "{}{}{:0width$}{}",
+                &format[..start],
+                prefix,
+                last,
+                &format[start + len..],
+                width = width
+            )
+        }
+        None => format!("{}{}{}", format, prefix, last),
+    }
+}
+
 #[derive(Debug, Clone)]
 struct LabelBox {
     x_min: f64,
@@ -106,9 +233,11 @@ struct RenderState {
     participant_x: HashMap<String, f64>,
     participant_widths: HashMap<String, f64>,
     current_y: f64,
-    activations: HashMap<String, Vec<(f64, Option<f64>)>>,
-    autonumber: Option<u32>,
+    activations: HashMap<String, Vec<Activation>>,
+    autonumber: Autonumbering,
     destroyed: HashMap<String, f64>,
+    /// Participants created mid-diagram and the top of their header
+    created: HashMap<String, f64>,
     has_title: bool,
     total_width: f64,
     /// Collected block backgrounds for deferred rendering
@@ -125,8 +254,16 @@ struct RenderState {
     parallel_depth: usize,
     /// Tracks message label bounding boxes to avoid overlap
     message_label_boxes: Vec<LabelBox>,
+    /// While drawing an `&` row, the first label box that belongs to the row
+    message_row_start: Option<usize>,
+    /// While drawing a message on an `&` row, how far its label moves below the arrow
+    message_row_offset: Option<f64>,
     /// Collected destroy X marks for deferred rendering (above activations)
     destroy_marks: Vec<(f64, f64)>,
+    /// Boxes grouping participant lifelines
+    participant_boxes: Vec<ParticipantBoxLayout>,
+    /// Vertical ranges of `...` delays, where lifelines are dotted
+    delay_gaps: Vec<(f64, f64)>,
 }
 
 // ============================================
@@ -152,6 +289,13 @@ fn group_spacing(config: &Config) -> f64 {
 // ============================================
 const MESSAGE_TEXT_ABOVE_ARROW: f64 = 6.0;       // Text is rendered 6px above arrow
 const DELAY_UNIT: f64 = 18.0;                    // Pixels per delay unit
+const DELAY_GAP_HEIGHT: f64 = 30.0;              // Dotted lifeline gap for `...`
+const SELF_MESSAGE_LOOP_WIDTH: f64 = 40.0;       // Horizontal reach of a self-message loop
+
+// ============================================
+// Activation
+// ============================================
+const ACTIVATION_NEST_OFFSET_RATIO: f64 = 0.5;   // Shift of each nested bar, in bar widths
 
 // ============================================
 // Block (alt, opt, loop, etc.)
@@ -166,6 +310,8 @@ const NOTE_MARGIN: f64 = 10.0;                   // Margin between note and life
 const NOTE_FOLD_SIZE: f64 = 8.0;                 // Corner fold size
 const NOTE_LINE_HEIGHT: f64 = 17.0;              // Line height (font 13px + 4px)
 const NOTE_MIN_WIDTH: f64 = 50.0;                // Minimum width
+const NOTE_HEXAGON_INSET: f64 = 10.0;            // Depth of a hexagon note's side points
+const LINK_NOTE_GAP: f64 = 4.0;                  // Gap between an arrow and its `note on link`
 
 // ============================================
 // State box
@@ -178,6 +324,35 @@ const STATE_PADDING: f64 = 12.0;                 // Padding inside state box (le
 // ============================================
 const REF_LINE_HEIGHT_EXTRA: f64 = 16.0;         // Extra line height for ref
 
+// ============================================
+// Participant glyphs
+// ============================================
+const DATABASE_CAP_HEIGHT: f64 = 8.0;            // Vertical radius of the cylinder caps
+const QUEUE_CAP_WIDTH: f64 = 8.0;                // Horizontal radius of the queue end caps
+const COLLECTIONS_OFFSET: f64 = 6.0;             // Offset of the back box in a stack
+const ICON_RADIUS: f64 = 12.0;                   // Circle radius of boundary/control/entity
+
+// ============================================
+// Participant box
+// ============================================
+const BOX_PADDING: f64 = 10.0;                   // Space between box edge and participant boxes
+const BOX_TITLE_HEIGHT: f64 = 22.0;              // Title row above the participant headers
+const BOX_GAP: f64 = 10.0;                       // Minimum space between a box edge and a neighbor
+
+// ============================================
+// Divider
+// ============================================
+const DIVIDER_LINE_GAP: f64 = 3.0;               // Distance between the two lines
+const DIVIDER_TEXT_PADDING: f64 = 10.0;          // Horizontal padding inside the label box
+
+// ============================================
+// Autonumber badge
+// ============================================
+const AUTONUMBER_BADGE_HEIGHT: f64 = 16.0;
+const AUTONUMBER_FONT_SIZE: f64 = 10.0;
+const AUTONUMBER_BADGE_PADDING: f64 = 4.0;       // Horizontal padding around the number
+const AUTONUMBER_BADGE_GAP: f64 = 2.0;           // Space between the arrow start and the badge
+
 // ============================================
 // Message label collision avoidance
 // ============================================
@@ -220,6 +395,10 @@ fn self_message_spacing(config: &Config, lines: usize) -> f64 {
     loop_height + group_spacing(config) + MESSAGE_TEXT_ABOVE_ARROW
 }
 
+fn divider_box_height(config: &Config) -> f64 {
+    config.font_size + 4.0 + ELEMENT_PADDING
+}
+
 fn note_line_height(_config: &Config) -> f64 {
     // Simplified: fixed value (font 13px + padding 4px = 17px)
     NOTE_LINE_HEIGHT
@@ -296,6 +475,93 @@ fn note_y_advance(config: &Config, line_count: usize) -> f64 {
     note_height.max(group_spacing(config)) + group_spacing(config) + MESSAGE_TEXT_ABOVE_ARROW
 }
 
+/// How far a label moves to get below its arrow when it collides with another
+/// label on the same `&` row
+fn row_label_drop(config: &Config, line_count: usize) -> f64 {
+    line_count as f64 * (config.font_size + 4.0) + MESSAGE_TEXT_ABOVE_ARROW * 2.0
+}
+
+/// How far each label on an `&` row moves below its arrow to clear the labels
+/// before it on the row; zero for labels that don't collide. `render_message`
+/// draws row labels with these offsets, so heights computed from them match.
+fn row_label_offsets(state: &RenderState, items: &[Item]) -> Vec<f64> {
+    let config = &state.config;
+    let line_height = config.font_size + 4.0;
+    let mut placed: Vec<LabelBox> = Vec::new();
+    items
+        .iter()
+        .map(|item| {
+            let Item::Message { from, to, text, arrow, .. } = item else {
+                return 0.0;
+            };
+            let lines: Vec<&str> = text.split("\\n").collect();
+            if from == to || lines.iter().all(|line| line.trim().is_empty()) {
+                return 0.0;
+            }
+            // Same label geometry as `render_message`, relative to the row's y
+            let extra_height = if lines.len() > 1 {
+                (lines.len() - 1) as f64 * line_height + MESSAGE_TEXT_ABOVE_ARROW
+            } else {
+                0.0
+            };
+            let delay_offset = arrow.delay.map_or(0.0, |d| d as f64 * DELAY_UNIT);
+            let text_y = extra_height + delay_offset / 2.0 - 6.0;
+            let text_x = (state.get_x(from) + state.get_x(to)) / 2.0;
+            let width = lines
+                .iter()
+                .map(|line| estimate_message_width(line, config.font_size))
+                .fold(0.0, f64::max);
+            let mut label = LabelBox {
+                x_min: text_x - width / 2.0,
+                x_max: text_x + width / 2.0,
+                y_min: text_y - (lines.len() - 1) as f64 * line_height - line_height * MESSAGE_LABEL_ASCENT_FACTOR,
+                y_max: text_y + line_height * MESSAGE_LABEL_DESCENT_FACTOR,
+            };
+            let step = row_label_drop(config, lines.len());
+            let mut offset = 0.0;
+            let mut attempts = 0;
+            while placed
+                .iter()
+                .any(|b| label_boxes_overlap(label.x_min, label.x_max, label.y_min, label.y_max, b))
+                && attempts < 20
+            {
+                label.y_min += step;
+                label.y_max += step;
+                offset += step;
+                attempts += 1;
+            }
+            placed.push(label);
+            offset
+        })
+        .collect()
+}
+
+/// Room below an `&` row for labels moved under their arrows
+fn message_row_label_room(state: &RenderState, kind: &BlockKind, items: &[Item]) -> f64 {
+    if *kind != BlockKind::Row {
+        return 0.0;
+    }
+    row_label_offsets(state, items).into_iter().fold(0.0, f64::max)
+}
+
+/// Extra Y advancement for a note attached to a message (`note on link`).
+/// The note hangs below a regular arrow or beside a self-message loop, so the
+/// row only grows by the part the next row's label would otherwise run into.
+fn link_note_y_extra(config: &Config, note: Option<&str>, is_self: bool, line_count: usize, delay_offset: f64) -> f64 {
+    let Some(note) = note else {
+        return 0.0;
+    };
+    let note_height = note_padding(config) * 2.0 + note.split("\\n").count() as f64 * note_line_height(config);
+    let (note_bottom, row_advance) = if is_self {
+        (note_height, self_message_spacing(config, line_count))
+    } else {
+        (delay_offset + LINK_NOTE_GAP + note_height, config.row_height + delay_offset)
+    };
+    // Room for the next label above its arrow
+    let clearance = ELEMENT_PADDING + config.font_size + MESSAGE_TEXT_ABOVE_ARROW;
+    (note_bottom + clearance - row_advance).max(0.0)
+}
+
 /// Calculate Y advancement for a state box
 fn state_y_advance(config: &Config, line_count: usize) -> f64 {
     let box_height = STATE_PADDING * 2.0 + line_count as f64 * state_line_height(config);
@@ -320,6 +586,32 @@ fn description_y_advance(config: &Config, line_count: usize) -> f64 {
     line_count as f64 * line_height + group_spacing(config) + MESSAGE_TEXT_ABOVE_ARROW
 }
 
+/// Calculate Y advancement for a section divider
+fn divider_y_advance(config: &Config) -> f64 {
+    divider_box_height(config) + group_spacing(config) + MESSAGE_TEXT_ABOVE_ARROW
+}
+
+/// Calculate Y advancement for a standalone `create` (the header row plus
+/// the extra row a `->*` message leaves below the created header)
+fn create_y_advance(config: &Config) -> f64 {
+    config.row_height * 2.0
+}
+
+/// Calculate Y advancement for a spacer (`|||` is one row)
+fn spacer_y_advance(config: &Config, height: Option<u32>) -> f64 {
+    height.map(f64::from).unwrap_or(config.row_height)
+}
+
+/// Calculate Y advancement for a `...` delay (dotted gap + row margin)
+fn delay_y_advance(config: &Config) -> f64 {
+    DELAY_GAP_HEIGHT + config.row_height
+}
+
+/// Top of a delay's dotted gap, halfway between the previous row and `current_y`
+fn delay_gap_top(config: &Config, current_y: f64) -> f64 {
+    current_y - config.row_height / 2.0
+}
+
 /// Calculate Y advancement for a block end (footer + row margin)
 fn block_end_y_advance(config: &Config, depth: usize) -> f64 {
     block_footer_padding(config, depth) + group_spacing(config)
@@ -356,17 +648,56 @@ fn arrowhead_points(x: f64, y: f64, direction: f64) -> String {
     )
 }
 
+/// Radius of circle endpoints (`o->`, `->o`)
+const ARROW_CIRCLE_RADIUS: f64 = 4.0;
+
+/// Half the width of cross endpoints (`->x`)
+const ARROW_CROSS_HALF_SIZE: f64 = 5.0;
+
+/// Half-arrowhead polygon points (one barb plus the shaft), with the barb on the upper side if `upper`
+fn half_arrowhead_points(x: f64, y: f64, direction: f64, upper: bool) -> String {
+    let size = ARROWHEAD_SIZE;
+    let half_width = size * 0.35;
+    let back_x = x - size * direction.cos();
+    let back_y = y - size * direction.sin();
+    let mut perp_x = -direction.sin() * half_width;
+    let mut perp_y = direction.cos() * half_width;
+    // Pick the side by screen position, so `-\` is the upper half in both directions
+    if (perp_y > 0.0) == upper {
+        perp_x = -perp_x;
+        perp_y = -perp_y;
+    }
+    format!(
+        "{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}",
+        back_x + perp_x,
+        back_y + perp_y,
+        x,
+        y,
+        back_x,
+        back_y
+    )
+}
+
+/// How far the message line stops short of the endpoint for a head
+fn arrowhead_inset(head: ArrowHead) -> f64 {
+    match head {
+        ArrowHead::Filled | ArrowHead::Open | ArrowHead::HalfUpper | ArrowHead::HalfLower => ARROWHEAD_SIZE,
+        ArrowHead::Cross => ARROW_CROSS_HALF_SIZE,
+        ArrowHead::Circle => ARROW_CIRCLE_RADIUS * 2.0,
+    }
+}
+
 /// Calculate direction angle from (x1, y1) to (x2, y2)
 fn arrow_direction(x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
     (y2 - y1).atan2(x2 - x1)
 }
 
 fn block_has_frame(kind: &BlockKind) -> bool {
-    !matches!(kind, BlockKind::Parallel | BlockKind::Serial)
+    !matches!(kind, BlockKind::Parallel | BlockKind::Serial | BlockKind::Row)
 }
 
 fn block_is_parallel(kind: &BlockKind) -> bool {
-    matches!(kind, BlockKind::Parallel)
+    matches!(kind, BlockKind::Parallel | BlockKind::Row)
 }
 
 fn parallel_needs_gap(items: &[Item]) -> bool {
@@ -418,7 +749,7 @@ fn participant_char_width(c: char) -> f64 {
 }
 
 /// Calculate participant box width based on WSD proportional font metrics
-fn calculate_participant_width(name: &str, min_width: f64) -> f64 {
+fn calculate_participant_width(name: &str, kind: ParticipantKind, min_width: f64) -> f64 {
     let lines: Vec<&str> = name.split("\\n").collect();
     let max_line_width = lines
         .iter()
@@ -428,7 +759,14 @@ fn calculate_participant_width(name: &str, min_width: f64) -> f64 {
     // WSD uses consistent padding for all participant boxes
     let padding = 50.0;
 
-    (max_line_width + padding).max(min_width)
+    // Shapes whose text area is narrower than their outline
+    let shape_extra = match kind {
+        ParticipantKind::Queue => QUEUE_CAP_WIDTH * 2.0,
+        ParticipantKind::Collections => COLLECTIONS_OFFSET,
+        _ => 0.0,
+    };
+
+    (max_line_width + padding + shape_extra).max(min_width)
 }
 
 fn max_weighted_line(text: &str) -> f64 {
@@ -452,53 +790,186 @@ fn block_tab_width(kind: &str) -> f64 {
     (kind.chars().count() as f64 * 12.0 + 21.0).max(57.0)
 }
 
+/// Text of a block's pentagon tab, e.g. `loop` or `ignore {ping, pong}`
+fn block_tab_text(kind: &BlockKind, messages: &[String]) -> String {
+    if messages.is_empty() {
+        kind.as_str().to_string()
+    } else {
+        format!("{} {{{}}}", kind.as_str(), messages.join(", "))
+    }
+}
+
+/// Resolve `box ... end box` groups to participant index ranges
+fn participant_box_layouts(participants: &[Participant], items: &[Item]) -> Vec<ParticipantBoxLayout> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Item::ParticipantBox {
+                title,
+                color,
+                participants: members,
+                ..
+            } => {
+                let indices: Vec<usize> = members
+                    .iter()
+                    .filter_map(|m| participants.iter().position(|p| p.id() == m))
+                    .collect();
+                Some(ParticipantBoxLayout {
+                    title: title.clone(),
+                    color: color.clone(),
+                    first: *indices.iter().min()?,
+                    last: *indices.iter().max()?,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Width needed for a participant box title
+fn box_title_width(title: &str, config: &Config) -> f64 {
+    estimate_message_width(title, config.font_size) + BOX_PADDING * 2.0
+}
+
+/// Convert a color as written in the source (`#lightblue`, `#e0f0ff`) to CSS
+fn css_color(color: &str) -> String {
+    let name = color.strip_prefix('#').unwrap_or(color);
+    let is_hex = matches!(name.len(), 3 | 4 | 6 | 8) && name.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hex {
+        format!("#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Whether a color from a style override is a plain token that cannot end its CSS declaration
+fn is_plain_color(color: &str) -> bool {
+    !color.is_empty() && color.chars().all(|c| c == '#' || c.is_ascii_alphanumeric())
+}
+
 /// Calculate note width based on text content (using note font size of 13px)
-fn calculate_note_width(text: &str, _config: &Config) -> f64 {
+fn calculate_note_width(text: &str, shape: NoteShape, _config: &Config) -> f64 {
     // Use NOTE_LINE_HEIGHT - 4.0 as the effective font size (13px)
     let note_font_size = NOTE_LINE_HEIGHT - 4.0;
     let text_width = estimate_text_width(text, note_font_size);
-    (ELEMENT_PADDING * 2.0 + text_width).max(NOTE_MIN_WIDTH)
+    let width = (ELEMENT_PADDING * 2.0 + text_width).max(NOTE_MIN_WIDTH);
+    // Hexagon points sit outside the text area
+    if shape == NoteShape::Hexagon {
+        width + NOTE_HEXAGON_INSET * 2.0
+    } else {
+        width
+    }
+}
+
+/// Lifeline x range covered by a note over several participants or across all of them
+fn note_span_x(
+    position: &NotePosition,
+    participants: &[String],
+    all_participants: &[Participant],
+    participant_x: &HashMap<String, f64>,
+) -> Option<(f64, f64)> {
+    let (first, last) = match position {
+        NotePosition::Over if participants.len() > 1 => (participants[0].as_str(), participants[participants.len() - 1].as_str()),
+        NotePosition::Across if all_participants.len() > 1 => {
+            (all_participants[0].id(), all_participants[all_participants.len() - 1].id())
+        }
+        _ => return None,
+    };
+    let x1 = *participant_x.get(first).unwrap_or(&0.0);
+    let x2 = *participant_x.get(last).unwrap_or(&0.0);
+    // Use min/max to handle any order of participants in the syntax
+    Some((x1.min(x2), x1.max(x2)))
 }
 
-/// Calculate required right margin based on right-side notes on the rightmost participant only
+/// Calculate required right margin based on right-side notes on the rightmost participant,
+/// and on notes over the lifelines or beside self-messages that reach past the rightmost
+/// participant box
 fn calculate_right_margin(
     participants: &[Participant],
     items: &[Item],
+    participant_x: &HashMap<String, f64>,
+    right_edge: f64,
     config: &Config,
 ) -> f64 {
-    let rightmost_id = match participants.last() {
-        Some(p) => p.id().to_string(),
-        None => return config.right_margin,
-    };
+    if participants.is_empty() {
+        return config.right_margin;
+    }
     let mut max_right_note_width: f64 = 0.0;
+    let mut max_overflow: f64 = 0.0;
 
     fn process_items_for_right_notes(
         items: &[Item],
-        rightmost_id: &str,
+        participants: &[Participant],
+        participant_x: &HashMap<String, f64>,
+        right_edge: f64,
         max_width: &mut f64,
+        max_overflow: &mut f64,
         config: &Config,
     ) {
+        let rightmost_id = participants.last().map(|p| p.id()).unwrap_or("");
         for item in items {
             match item {
                 Item::Note {
                     position: NotePosition::Right,
-                    participants,
+                    shape,
+                    participants: note_participants,
                     text,
+                    ..
                 } => {
                     // Only consider notes on the rightmost participant
-                    if participants.first().map(|s| s.as_str()) == Some(rightmost_id) {
-                        let note_width = calculate_note_width(text, config);
+                    if note_participants.first().map(|s| s.as_str()) == Some(rightmost_id) {
+                        let note_width = calculate_note_width(text, *shape, config);
                         if note_width > *max_width {
                             *max_width = note_width;
                         }
                     }
                 }
+                Item::Note {
+                    position: position @ (NotePosition::Over | NotePosition::Across),
+                    shape,
+                    participants: note_participants,
+                    text,
+                    ..
+                } => {
+                    // Spanning notes start at the left lifeline and grow to the right,
+                    // single notes are centered on their lifeline
+                    let note_width = calculate_note_width(text, *shape, config);
+                    let note_right = match note_span_x(position, note_participants, participants, participant_x) {
+                        Some((left_x, right_x)) => left_x - NOTE_MARGIN + note_width.max(right_x - left_x + NOTE_MARGIN * 2.0),
+                        None => {
+                            let id = note_participants.first().map(|s| s.as_str()).unwrap_or(rightmost_id);
+                            participant_x.get(id).map_or(0.0, |x| x + note_width / 2.0)
+                        }
+                    };
+                    *max_overflow = max_overflow.max(note_right - right_edge);
+                }
+                Item::Message {
+                    from,
+                    to,
+                    note: Some(note),
+                    ..
+                } if from == to => {
+                    // `note on link` beside a self-message loop
+                    let note_width = calculate_note_width(note, NoteShape::Folded, config);
+                    let note_right = participant_x.get(from).map_or(0.0, |x| {
+                        x + config.activation_width / 2.0 + SELF_MESSAGE_LOOP_WIDTH + NOTE_MARGIN + note_width
+                    });
+                    *max_overflow = max_overflow.max(note_right - right_edge);
+                }
                 Item::Block {
                     items, else_sections, ..
                 } => {
-                    process_items_for_right_notes(items, rightmost_id, max_width, config);
+                    process_items_for_right_notes(items, participants, participant_x, right_edge, max_width, max_overflow, config);
                     for section in else_sections {
-                        process_items_for_right_notes(&section.items, rightmost_id, max_width, config);
+                        process_items_for_right_notes(
+                            &section.items,
+                            participants,
+                            participant_x,
+                            right_edge,
+                            max_width,
+                            max_overflow,
+                            config,
+                        );
                     }
                 }
                 _ => {}
@@ -506,14 +977,23 @@ fn calculate_right_margin(
         }
     }
 
-    process_items_for_right_notes(items, &rightmost_id, &mut max_right_note_width, config);
+    process_items_for_right_notes(
+        items,
+        participants,
+        participant_x,
+        right_edge,
+        &mut max_right_note_width,
+        &mut max_overflow,
+        config,
+    );
 
     // right_margin needs to accommodate: NOTE_MARGIN + note_width
-    if max_right_note_width > 0.0 {
+    let note_margin = if max_right_note_width > 0.0 {
         (max_right_note_width + NOTE_MARGIN).max(config.right_margin)
     } else {
         config.right_margin
-    }
+    };
+    note_margin.max(max_overflow)
 }
 
 /// Calculate required left margin based on left-side notes and self-message text
@@ -540,12 +1020,14 @@ fn calculate_left_margin(
             match item {
                 Item::Note {
                     position: NotePosition::Left,
+                    shape,
                     participants,
                     text,
+                    ..
                 } => {
                     // Only consider notes on the leftmost participant
                     if participants.first().map(|s| s.as_str()) == Some(leftmost_id) {
-                        let note_width = calculate_note_width(text, config);
+                        let note_width = calculate_note_width(text, *shape, config);
                         if note_width > *max_note_width {
                             *max_note_width = note_width;
                         }
@@ -620,11 +1102,24 @@ fn calculate_participant_gaps(
         items: &[Item],
         participant_index: &HashMap<String, usize>,
         gaps: &mut Vec<f64>,
+        numbering: &mut Autonumbering,
         config: &Config,
     ) {
         for item in items {
             match item {
-                Item::Message { from, to, text, arrow, .. } => {
+                Item::Message {
+                    from,
+                    to,
+                    text,
+                    arrow,
+                    note,
+                    ..
+                } => {
+                    // The badge sits at the arrow start, beside the centered label
+                    let badge_width = numbering
+                        .next_label()
+                        .map(|label| (autonumber_badge_width(&label) + AUTONUMBER_BADGE_GAP) * 2.0)
+                        .unwrap_or(0.0);
                     if let (Some(&from_idx), Some(&to_idx)) =
                         (participant_index.get(from), participant_index.get(to))
                     {
@@ -635,7 +1130,11 @@ fn calculate_participant_gaps(
                                 (to_idx, from_idx)
                             };
 
-                            let text_width = estimate_message_width(text, config.font_size);
+                            // A `note on link` is centered below the label
+                            let note_width = note
+                                .as_deref()
+                                .map_or(0.0, |note| calculate_note_width(note, NoteShape::Folded, config));
+                            let text_width = estimate_message_width(text, config.font_size).max(note_width) + badge_width;
 
                             // WSD: delay messages need extra horizontal space for diagonal lines
                             // Delay coefficient 86.4 for WSD gap matching (645px for delay(7))
@@ -665,11 +1164,13 @@ fn calculate_participant_gaps(
                 }
                 Item::Note {
                     position,
+                    shape,
                     participants: note_participants,
                     text,
+                    ..
                 } => {
                     // Calculate note width
-                    let note_width = calculate_note_width(text, config);
+                    let note_width = calculate_note_width(text, *shape, config);
 
                     if let Some(participant) = note_participants.first() {
                         if let Some(&idx) = participant_index.get(participant) {
@@ -693,7 +1194,7 @@ fn calculate_participant_gaps(
                                         }
                                     }
                                 }
-                                NotePosition::Over => {
+                                NotePosition::Over | NotePosition::Across => {
                                     // Over note: only process if spanning multiple participants
                                     // Single participant case is fine as long as width doesn't exceed
                                 }
@@ -704,17 +1205,18 @@ fn calculate_participant_gaps(
                 Item::Block {
                     items, else_sections, ..
                 } => {
-                    process_items(items, participant_index, gaps, config);
+                    process_items(items, participant_index, gaps, numbering, config);
                     for section in else_sections {
-                        process_items(&section.items, participant_index, gaps, config);
+                        process_items(&section.items, participant_index, gaps, numbering, config);
                     }
                 }
+                Item::Autonumber { command, .. } => numbering.apply(command),
                 _ => {}
             }
         }
     }
 
-    process_items(items, &participant_index, &mut gaps, config);
+    process_items(items, &participant_index, &mut gaps, &mut Autonumbering::default(), config);
 
     // WSD: participant name lengths don't directly increase gaps
     // The participant box widths (already calculated elsewhere) handle this
@@ -728,9 +1230,59 @@ fn calculate_participant_gaps(
         }
     }
 
+    apply_participant_box_gaps(participants, items, &mut gaps, config);
+
     gaps
 }
 
+/// Widen gaps so participant boxes get their padding and fit their titles
+fn apply_participant_box_gaps(participants: &[Participant], items: &[Item], gaps: &mut [f64], config: &Config) {
+    let boxes = participant_box_layouts(participants, items);
+    if boxes.is_empty() {
+        return;
+    }
+    let widths: Vec<f64> = participants
+        .iter()
+        .map(|p| calculate_participant_width(&p.name, p.kind, config.participant_width))
+        .collect();
+
+    // Box edges between two neighbors need room for the padding on each side
+    for (i, gap) in gaps.iter_mut().enumerate() {
+        let edges = boxes.iter().filter(|b| b.last == i).count() + boxes.iter().filter(|b| b.first == i + 1).count();
+        if edges > 0 {
+            let needed = (widths[i] + widths[i + 1]) / 2.0 + edges as f64 * BOX_PADDING + BOX_GAP;
+            *gap = gap.max(needed);
+        }
+    }
+
+    // Spread any extra title width over the gaps inside the box, or around
+    // a single-member box
+    for b in &boxes {
+        let Some(title) = &b.title else {
+            continue;
+        };
+        let inner: f64 = gaps[b.first..b.last].iter().sum();
+        let span = inner + (widths[b.first] + widths[b.last]) / 2.0 + BOX_PADDING * 2.0;
+        let extra = box_title_width(title, config) - span;
+        if extra <= 0.0 {
+            continue;
+        }
+        if b.last > b.first {
+            let per_gap = extra / (b.last - b.first) as f64;
+            for gap in &mut gaps[b.first..b.last] {
+                *gap += per_gap;
+            }
+        } else {
+            if b.first > 0 {
+                gaps[b.first - 1] += extra / 2.0;
+            }
+            if b.last < gaps.len() {
+                gaps[b.last] += extra / 2.0;
+            }
+        }
+    }
+}
+
 impl RenderState {
     fn new(
         config: Config,
@@ -756,7 +1308,10 @@ impl RenderState {
                         108.0 // WSD uses fixed 108px for multi-line
                     }
                 }
-                ParticipantKind::Actor => {
+                ParticipantKind::Actor
+                | ParticipantKind::Boundary
+                | ParticipantKind::Control
+                | ParticipantKind::Entity => {
                     // WSD: Actor has stick figure + name below
                     // ~85px for 1-line, ~108px for 2+ lines
                     if lines <= 1 {
@@ -765,6 +1320,21 @@ impl RenderState {
                         108.0
                     }
                 }
+                ParticipantKind::Database => {
+                    // Cylinder caps take room above and below the name
+                    if lines <= 1 {
+                        46.0 + DATABASE_CAP_HEIGHT * 2.0
+                    } else {
+                        108.0
+                    }
+                }
+                ParticipantKind::Queue | ParticipantKind::Collections => {
+                    if lines <= 1 {
+                        46.0
+                    } else {
+                        108.0
+                    }
+                }
             };
             if needed > required_header_height {
                 required_header_height = needed;
@@ -779,16 +1349,15 @@ impl RenderState {
         let min_width = config.participant_width;
 
         for p in &participants {
-            let width = calculate_participant_width(&p.name, min_width);
+            let width = calculate_participant_width(&p.name, p.kind, min_width);
             participant_widths.insert(p.id().to_string(), width);
         }
 
         let gaps = calculate_participant_gaps(&participants, items, &config);
+        let participant_boxes = participant_box_layouts(&participants, items);
 
         // Left margin for notes on leftmost participant (dynamic)
         let left_margin = calculate_left_margin(&participants, items, &config);
-        // Right margin for self-loops and notes on rightmost participant (dynamic)
-        let right_margin = calculate_right_margin(&participants, items, &config);
 
         let mut participant_x = HashMap::new();
         let first_width = participants
@@ -883,7 +1452,10 @@ impl RenderState {
             .last()
             .map(|p| *participant_widths.get(p.id()).unwrap_or(&min_width))
             .unwrap_or(min_width);
-        let total_width = current_x + last_width / 2.0 + right_margin + config.padding;
+        // Right margin for notes reaching past the rightmost participant (dynamic)
+        let right_edge = current_x + last_width / 2.0;
+        let right_margin = calculate_right_margin(&participants, items, &participant_x, right_edge, &config);
+        let total_width = right_edge + right_margin + config.padding;
 
         Self {
             config,
@@ -892,8 +1464,9 @@ impl RenderState {
             participant_widths,
             current_y: 0.0,
             activations: HashMap::new(),
-            autonumber: None,
+            autonumber: Autonumbering::default(),
             destroyed: HashMap::new(),
+            created: HashMap::new(),
             has_title,
             total_width,
             block_backgrounds: Vec::new(),
@@ -903,7 +1476,11 @@ impl RenderState {
             serial_first_row_pending: Vec::new(),
             parallel_depth: 0,
             message_label_boxes: Vec::new(),
+            message_row_start: None,
+            message_row_offset: None,
             destroy_marks: Vec::new(),
+            participant_boxes,
+            delay_gaps: Vec::new(),
         }
     }
 
@@ -970,8 +1547,7 @@ impl RenderState {
         // Only check collision with boxes that could actually overlap
         // Skip boxes whose y_max is significantly above our y_min (they're in previous rows)
         let relevance_threshold = step * 2.0;
-        let relevant_boxes: Vec<&LabelBox> = self
-            .message_label_boxes
+        let relevant_boxes: Vec<&LabelBox> = self.message_label_boxes[self.message_row_start.unwrap_or(0)..]
             .iter()
             .filter(|b| b.y_max + relevance_threshold >= y_min)
             .collect();
@@ -1007,45 +1583,79 @@ impl RenderState {
         }
     }
 
-    /// Check if a participant has an active activation at the given Y position
-    fn is_participant_active_at(&self, participant: &str, y: f64) -> bool {
-        if let Some(acts) = self.activations.get(participant) {
-            acts.iter().any(|(start_y, end_y)| {
-                *start_y <= y && end_y.map_or(true, |end| y <= end)
-            })
-        } else {
-            false
+    /// Depth a new activation of the participant would get (its open activations)
+    fn next_activation_depth(&self, participant: &str) -> usize {
+        self.activations
+            .get(participant)
+            .map_or(0, |acts| acts.iter().filter(|act| act.end_y.is_none()).count())
+    }
+
+    /// Open an activation bar, nested inside the participant's open bars
+    fn activate(&mut self, participant: &str, y: f64, color: Option<&str>) {
+        let depth = self.next_activation_depth(participant);
+        self.activations
+            .entry(participant.to_string())
+            .or_default()
+            .push(Activation {
+                start_y: y,
+                end_y: None,
+                depth,
+                color: color.map(str::to_string),
+            });
+    }
+
+    /// Close the participant's innermost open activation bar
+    fn deactivate(&mut self, participant: &str, y: f64) {
+        let innermost = self
+            .activations
+            .get_mut(participant)
+            .and_then(|acts| acts.iter_mut().rev().find(|act| act.end_y.is_none()));
+        if let Some(act) = innermost {
+            act.end_y = Some(y);
         }
     }
 
-    /// Get arrow start X position, accounting for activation bar
-    fn get_arrow_start_x(&self, participant: &str, y: f64, going_right: bool) -> f64 {
-        let x = self.get_x(participant);
-        if self.is_participant_active_at(participant, y) {
-            let half_width = self.config.activation_width / 2.0;
-            if going_right {
-                x + half_width // Arrow starts from right edge of activation bar
-            } else {
-                x - half_width // Arrow starts from left edge of activation bar
+    /// Depth of the innermost activation bar at the given Y position
+    fn activation_depth_at(&self, participant: &str, y: f64) -> Option<usize> {
+        self.activations
+            .get(participant)?
+            .iter()
+            .filter(|act| act.start_y <= y && act.end_y.map_or(true, |end| y <= end))
+            .map(|act| act.depth)
+            .max()
+    }
+
+    /// Center X of an activation bar, nested bars shifted to the right
+    fn activation_center_x(&self, participant: &str, depth: usize) -> f64 {
+        self.get_x(participant) + depth as f64 * self.config.activation_width * ACTIVATION_NEST_OFFSET_RATIO
+    }
+
+    /// X of the innermost activation bar's edge at `y`, or the lifeline when inactive
+    fn activation_edge_x(&self, participant: &str, y: f64, right: bool) -> f64 {
+        match self.activation_depth_at(participant, y) {
+            Some(depth) => {
+                let half_width = self.config.activation_width / 2.0;
+                let center = self.activation_center_x(participant, depth);
+                if right {
+                    center + half_width
+                } else {
+                    center - half_width
+                }
             }
-        } else {
-            x
+            None => self.get_x(participant),
         }
     }
 
-    /// Get arrow end X position, accounting for activation bar
+    /// Get arrow start X position, accounting for activation bars
+    fn get_arrow_start_x(&self, participant: &str, y: f64, going_right: bool) -> f64 {
+        // Arrow starts from the innermost bar's edge facing the receiver
+        self.activation_edge_x(participant, y, going_right)
+    }
+
+    /// Get arrow end X position, accounting for activation bars
     fn get_arrow_end_x(&self, participant: &str, y: f64, coming_from_right: bool) -> f64 {
-        let x = self.get_x(participant);
-        if self.is_participant_active_at(participant, y) {
-            let half_width = self.config.activation_width / 2.0;
-            if coming_from_right {
-                x + half_width // Arrow ends at right edge of activation bar
-            } else {
-                x - half_width // Arrow ends at left edge of activation bar
-            }
-        } else {
-            x
-        }
+        // Arrow ends at the innermost bar's edge facing the sender
+        self.activation_edge_x(participant, y, coming_from_right)
     }
 
     fn diagram_width(&self) -> f64 {
@@ -1101,10 +1711,31 @@ impl RenderState {
     }
 
     fn header_top(&self) -> f64 {
-        if self.has_title {
+        let top = if self.has_title {
             self.config.padding + self.config.title_height
         } else {
             self.config.padding
+        };
+        top + self.box_header_space()
+    }
+
+    /// Space above the participant headers taken by participant boxes
+    fn box_header_space(&self) -> f64 {
+        if self.participant_boxes.is_empty() {
+            0.0
+        } else if self.participant_boxes.iter().any(|b| b.title.is_some()) {
+            BOX_PADDING + BOX_TITLE_HEIGHT
+        } else {
+            BOX_PADDING
+        }
+    }
+
+    /// Space below the participant footers taken by participant boxes
+    fn box_footer_space(&self) -> f64 {
+        if self.participant_boxes.is_empty() {
+            0.0
+        } else {
+            BOX_PADDING
         }
     }
 
@@ -1114,11 +1745,8 @@ impl RenderState {
         self.header_top() + self.config.header_height + self.config.row_height
     }
 
-    fn next_number(&mut self) -> Option<u32> {
-        self.autonumber.map(|n| {
-            self.autonumber = Some(n + 1);
-            n
-        })
+    fn next_number(&mut self) -> Option<String> {
+        self.autonumber.next_label()
     }
 
     /// Add a block background to be rendered later
@@ -1210,6 +1838,21 @@ fn find_involved_participants(items: &[Item], state: &RenderState) -> Option<(f6
                         leftmost_id,
                     );
                 }
+                Item::Note {
+                    position: NotePosition::Across,
+                    ..
+                } => {
+                    for p in &state.participants {
+                        update_bounds(
+                            p.id(),
+                            state,
+                            min_left,
+                            max_right,
+                            includes_leftmost,
+                            leftmost_id,
+                        );
+                    }
+                }
                 Item::Note { participants, .. } => {
                     for p in participants {
                         update_bounds(
@@ -1244,9 +1887,10 @@ fn find_involved_participants(items: &[Item], state: &RenderState) -> Option<(f6
                         );
                     }
                 }
-                Item::Activate { participant }
-                | Item::Deactivate { participant }
-                | Item::Destroy { participant } => {
+                Item::Activate { participant, .. }
+                | Item::Deactivate { participant, .. }
+                | Item::Destroy { participant, .. }
+                | Item::Create { participant, .. } => {
                     update_bounds(
                         participant,
                         state,
@@ -1256,10 +1900,23 @@ fn find_involved_participants(items: &[Item], state: &RenderState) -> Option<(f6
                         leftmost_id,
                     );
                 }
-                _ => {}
-            }
-        }
-    }
+                Item::Divider { .. } => {
+                    // A divider spans every lifeline, so its block does too
+                    for p in [state.participants.first(), state.participants.last()].into_iter().flatten() {
+                        update_bounds(
+                            p.id(),
+                            state,
+                            min_left,
+                            max_right,
+                            includes_leftmost,
+                            leftmost_id,
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
 
     process_items(
         items,
@@ -1392,6 +2049,7 @@ fn collect_block_backgrounds(
                 activate,
                 deactivate,
                 create,
+                note,
                 ..
             } => {
                 state.apply_else_return_gap(arrow);
@@ -1404,6 +2062,7 @@ fn collect_block_backgrounds(
                 } else {
                     state.current_y += regular_message_y_advance(&state.config, line_count, delay_offset);
                 }
+                state.current_y += link_note_y_extra(&state.config, note.as_deref(), is_self, line_count, delay_offset);
 
                 if *create {
                     state.current_y += state.config.row_height;
@@ -1429,13 +2088,27 @@ fn collect_block_backgrounds(
                 let line_count = text.split("\\n").count();
                 state.current_y += ref_y_advance(&state.config, line_count);
             }
-            Item::Description { text } => {
+            Item::Description { text, .. } => {
                 let line_count = text.split("\\n").count();
                 state.current_y += description_y_advance(&state.config, line_count);
             }
+            Item::Divider { .. } => {
+                state.current_y += divider_y_advance(&state.config);
+            }
+            Item::Spacer { height, .. } => {
+                state.current_y += spacer_y_advance(&state.config, *height);
+            }
+            Item::Delay { .. } => {
+                let top = delay_gap_top(&state.config, state.current_y);
+                state.delay_gaps.push((top, top + DELAY_GAP_HEIGHT));
+                state.current_y += delay_y_advance(&state.config);
+            }
             Item::Destroy { .. } => {
                 state.current_y += state.config.row_height;
             }
+            Item::Create { .. } => {
+                state.current_y += create_y_advance(&state.config);
+            }
             Item::Activate { .. } => {
                 *active_activation_count += 1;
             }
@@ -1447,8 +2120,11 @@ fn collect_block_backgrounds(
             Item::Block {
                 kind,
                 label,
+                messages,
+                condition,
                 items,
                 else_sections,
+                ..
             } => {
                 if block_is_parallel(kind) {
                     state.push_parallel();
@@ -1475,7 +2151,7 @@ fn collect_block_backgrounds(
                     } else {
                         0.0
                     };
-                    state.current_y = max_end_y + gap;
+                    state.current_y = max_end_y + gap + message_row_label_room(state, kind, items);
                     state.pop_parallel();
                     continue;
                 }
@@ -1514,16 +2190,33 @@ fn collect_block_backgrounds(
                 let frame_shift = block_frame_shift(depth);
                 let frame_start_y = start_y - frame_shift;
 
-                // Calculate bounds based on involved participants and label width
-                let (x1, x2) = calculate_block_bounds_with_label(
-                    items,
-                    else_sections,
-                    label,
-                    kind.as_str(),
-                    depth,
-                    state,
-                    parent_bounds,
-                );
+                // A group shows its own label in the tab and the optional condition beside it
+                let (tab_text, label) = if *kind == BlockKind::Group {
+                    let tab_text = if label.is_empty() {
+                        kind.as_str().to_string()
+                    } else {
+                        label.clone()
+                    };
+                    (tab_text, condition.as_deref().unwrap_or(""))
+                } else {
+                    (block_tab_text(kind, messages), label.as_str())
+                };
+
+                // Calculate bounds based on involved participants and label width.
+                // A break frame covers every lifeline of its enclosing fragment.
+                let (x1, x2) = match (kind, parent_bounds) {
+                    (BlockKind::Break, Some((px1, px2))) => (px1 + NESTED_BLOCK_INSET, px2 - NESTED_BLOCK_INSET),
+                    (BlockKind::Break, None) => (state.block_left(), state.block_right()),
+                    _ => calculate_block_bounds_with_label(
+                        items,
+                        else_sections,
+                        label,
+                        &tab_text,
+                        depth,
+                        state,
+                        parent_bounds,
+                    ),
+                };
 
                 state.current_y += block_header_space(&state.config, depth);
                 // Pass current block bounds as parent bounds to nested blocks
@@ -1565,7 +2258,7 @@ fn collect_block_backgrounds(
                     frame_start_y,
                     frame_end_y,
                     x2,
-                    kind.as_str(),
+                    &tab_text,
                     label,
                     else_section_info,
                 );
@@ -1575,6 +2268,54 @@ fn collect_block_backgrounds(
     }
 }
 
+/// Render participant boxes from below the title to the bottom of the footers
+fn render_participant_boxes(svg: &mut String, state: &RenderState, total_height: f64) {
+    let top = state.header_top() - state.box_header_space();
+    let bottom = total_height - state.config.padding;
+    for b in &state.participant_boxes {
+        let first = state.participants[b.first].id();
+        let last = state.participants[b.last].id();
+        let mut x1 = state.get_x(first) - state.get_participant_width(first) / 2.0 - BOX_PADDING;
+        let mut x2 = state.get_x(last) + state.get_participant_width(last) / 2.0 + BOX_PADDING;
+        if let Some(title) = &b.title {
+            let extra = box_title_width(title, &state.config) - (x2 - x1);
+            if extra > 0.0 {
+                x1 -= extra / 2.0;
+                x2 += extra / 2.0;
+            }
+        }
+        let x1 = x1.max(state.config.padding / 2.0);
+        let x2 = x2.min(state.total_width - state.config.padding / 2.0);
+
+        let style = b
+            .color
+            .as_ref()
+            .map(|c| format!(r#" style="fill: {}""#, escape_xml(&css_color(c))))
+            .unwrap_or_default();
+        writeln!(
+            svg,
+            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="participant-box"{style}/>"#,
+            x = x1,
+            y = top,
+            w = x2 - x1,
+            h = bottom - top,
+            style = style
+        )
+        .unwrap();
+
+        if let Some(title) = &b.title {
+            writeln!(
+                svg,
+                r#"<text x="{x}" y="{y}" class="participant-box-title">{t}</text>"#,
+                x = (x1 + x2) / 2.0,
+                y = top + BOX_TITLE_HEIGHT - 4.0,
+                t = escape_xml(title)
+            )
+            .unwrap();
+        }
+    }
+}
+
 /// Render all collected block backgrounds
 fn render_block_backgrounds(svg: &mut String, state: &RenderState) {
     let theme = &state.config.theme;
@@ -1647,7 +2388,7 @@ fn render_block_labels(svg: &mut String, state: &RenderState) {
             r#"<text x="{x}" y="{y}" class="block-label">{kind}</text>"#,
             x = x1 + 5.0,
             y = start_y + label_text_offset,
-            kind = label_text
+            kind = escape_xml(label_text)
         )
         .unwrap();
 
@@ -1719,7 +2460,7 @@ pub fn render_with_config(diagram: &Diagram, config: Config) -> String {
     let mut svg = String::new();
 
     // Pre-calculate height
-    let content_height = calculate_height(&diagram.items, &state.config, 0);
+    let content_height = calculate_height(&diagram.items, &state, 0);
     let title_space = if has_title {
         state.config.title_height
     } else {
@@ -1736,6 +2477,8 @@ pub fn render_with_config(diagram: &Diagram, config: Config) -> String {
     let footer_margin = state.config.row_height; // Space between content and footer
     let base_total_height = state.config.padding * 2.0
         + title_space
+        + state.box_header_space()
+        + state.box_footer_space()
         + state.config.header_height
         + content_height
         + footer_margin
@@ -1838,6 +2581,20 @@ pub fn render_with_config(diagram: &Diagram, config: Config) -> String {
         c = theme.message_text_color
     )
     .unwrap();
+    writeln!(
+        &mut svg,
+        ".participant-box {{ fill: none; stroke: {c}; stroke-width: 1; }}",
+        c = theme.block_stroke
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".participant-box-title {{ font-family: {f}; font-size: {s}px; font-weight: bold; text-anchor: middle; fill: {c}; }}",
+        f = theme.font_family,
+        s = state.config.font_size - 1.0,
+        c = theme.message_text_color
+    )
+    .unwrap();
     writeln!(
         &mut svg,
         ".activation {{ fill: {fill}; stroke: {stroke}; stroke-width: 1; }}",
@@ -1879,6 +2636,61 @@ pub fn render_with_config(diagram: &Diagram, config: Config) -> String {
         c = theme.message_color
     )
     .unwrap();
+    writeln!(
+        &mut svg,
+        ".arrowhead-cross {{ fill: none; stroke: {c}; stroke-width: 1.5; }}",
+        c = theme.message_color
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".autonumber-badge {{ fill: {c}; stroke: none; }}",
+        c = theme.autonumber_fill
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".autonumber-text {{ font-family: {f}; font-size: {s}px; font-weight: bold; fill: {c}; }}",
+        f = theme.font_family,
+        s = AUTONUMBER_FONT_SIZE,
+        c = theme.autonumber_text_color
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".lifeline-delay {{ stroke: {c}; stroke-width: 1.5; stroke-dasharray: 1,4; }}",
+        c = theme.lifeline_color
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".delay-text {{ font-family: {f}; font-size: {s}px; font-style: italic; fill: {c}; }}",
+        f = theme.font_family,
+        s = state.config.font_size,
+        c = theme.description_text_color
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".divider-line {{ stroke: {c}; stroke-width: 1; }}",
+        c = theme.block_stroke
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".divider-box {{ fill: {f}; stroke: {c}; stroke-width: 1; }}",
+        f = theme.block_label_fill,
+        c = theme.block_stroke
+    )
+    .unwrap();
+    writeln!(
+        &mut svg,
+        ".divider-text {{ font-family: {f}; font-size: {s}px; font-weight: bold; fill: {c}; }}",
+        f = theme.font_family,
+        s = state.config.font_size,
+        c = theme.message_text_color
+    )
+    .unwrap();
     svg.push_str("</style>\n");
     svg.push_str("</defs>\n");
 
@@ -1906,10 +2718,15 @@ pub fn render_with_config(diagram: &Diagram, config: Config) -> String {
     // Calculate footer position
     let header_y = state.header_top();
     let footer_y = match footer_style {
-        FooterStyle::Box => base_total_height - state.config.padding - state.config.header_height,
-        FooterStyle::Bar | FooterStyle::None => total_height - state.config.padding,
+        FooterStyle::Box => {
+            base_total_height - state.box_footer_space() - state.config.padding - state.config.header_height
+        }
+        FooterStyle::Bar | FooterStyle::None => total_height - state.box_footer_space() - state.config.padding,
     };
 
+    // Participant boxes go behind everything else
+    render_participant_boxes(&mut svg, &state, total_height);
+
     // Draw block backgrounds FIRST (behind lifelines)
     // (block_backgrounds already calculated above for width calculation)
     render_block_backgrounds(&mut svg, &state);
@@ -1918,32 +2735,43 @@ pub fn render_with_config(diagram: &Diagram, config: Config) -> String {
     state.current_y = state.content_start();
 
     // Pre-calculate destroyed participants for lifeline rendering
-    let destroyed_map = collect_destroyed(&diagram.items, &state.config, state.content_start());
+    let destroyed_map = collect_destroyed(&diagram.items, &state, state.content_start());
+
+    // Render items first so the headers of created participants are placed,
+    // then draw them above the lifelines
+    let mut items_svg = String::new();
+    render_items(&mut items_svg, &mut state, &diagram.items, 0);
 
     // Draw lifelines (behind messages but above block backgrounds)
-    let lifeline_start = header_y + state.config.header_height;
     let lifeline_end = footer_y;
 
     for p in &state.participants {
         let x = state.get_x(p.id());
+        // A created participant's lifeline starts below its header
+        let header_top = state.created.get(p.id()).copied().unwrap_or(header_y);
+        let lifeline_start = header_top + state.config.header_height;
         // If participant is destroyed, end lifeline at destroy position
         let end_y = destroyed_map.get(p.id()).copied().unwrap_or(lifeline_end);
-        writeln!(
-            &mut svg,
-            r#"<line x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" class="lifeline"/>"#,
-            x = x,
-            y1 = lifeline_start,
-            y2 = end_y
-        )
-        .unwrap();
+        let style = p
+            .style
+            .lifeline
+            .as_ref()
+            .map(|c| format!(r#" style="stroke: {}""#, escape_xml(&css_color(c))))
+            .unwrap_or_default();
+        for (y1, y2, dotted) in lifeline_segments(lifeline_start, end_y, &state.delay_gaps) {
+            writeln!(
+                &mut svg,
+                r#"<line x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" class="{class}"{style}/>"#,
+                class = if dotted { "lifeline-delay" } else { "lifeline" }
+            )
+            .unwrap();
+        }
     }
 
     // Draw participant headers
     render_participant_headers(&mut svg, &state, header_y);
 
-    // Render items
-    state.current_y = state.content_start();
-    render_items(&mut svg, &mut state, &diagram.items, 0);
+    svg.push_str(&items_svg);
 
     // Draw activation bars
     render_activations(&mut svg, &mut state, footer_y);
@@ -1989,16 +2817,17 @@ pub fn render_with_config(diagram: &Diagram, config: Config) -> String {
     svg
 }
 
-fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
+fn calculate_height(items: &[Item], state: &RenderState, depth: usize) -> f64 {
     fn inner(
         items: &[Item],
-        config: &Config,
+        state: &RenderState,
         depth: usize,
         else_pending: &mut Vec<bool>,
         serial_pending: &mut Vec<bool>,
         active_activation_count: &mut usize,
         parallel_depth: &mut usize,
     ) -> f64 {
+        let config = &state.config;
         let mut height = 0.0;
         for item in items {
             match item {
@@ -2010,6 +2839,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                     create,
                     activate,
                     deactivate,
+                    note,
                     ..
                 } => {
                     if let Some(pending) = else_pending.last_mut() {
@@ -2025,6 +2855,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                     } else {
                         height += regular_message_y_advance(config, line_count, delay_offset);
                     }
+                    height += link_note_y_extra(config, note.as_deref(), is_self, line_count, delay_offset);
                     if *create {
                         height += config.row_height;
                     }
@@ -2053,10 +2884,19 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                     let line_count = text.split("\\n").count();
                     height += ref_y_advance(config, line_count);
                 }
-                Item::Description { text } => {
+                Item::Description { text, .. } => {
                     let line_count = text.split("\\n").count();
                     height += description_y_advance(config, line_count);
                 }
+                Item::Divider { .. } => {
+                    height += divider_y_advance(config);
+                }
+                Item::Spacer { height: spacer, .. } => {
+                    height += spacer_y_advance(config, *spacer);
+                }
+                Item::Delay { .. } => {
+                    height += delay_y_advance(config);
+                }
                 Item::Block {
                     kind,
                     items,
@@ -2071,7 +2911,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                             *active_activation_count = base_activation_count;
                             let branch_height = inner(
                                 std::slice::from_ref(item),
-                                config,
+                                state,
                                 depth,
                                 else_pending,
                                 serial_pending,
@@ -2091,7 +2931,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                         } else {
                             0.0
                         };
-                        height += max_branch_height + gap;
+                        height += max_branch_height + gap + message_row_label_room(state, kind, items);
                         continue;
                     }
 
@@ -2099,7 +2939,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                         serial_pending.push(true);
                         height += inner(
                             items,
-                            config,
+                            state,
                             depth,
                             else_pending,
                             serial_pending,
@@ -2109,7 +2949,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                         for else_section in else_sections {
                             height += inner(
                                 &else_section.items,
-                                config,
+                                state,
                                 depth,
                                 else_pending,
                                 serial_pending,
@@ -2121,7 +2961,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                     } else if !block_has_frame(kind) {
                         height += inner(
                             items,
-                            config,
+                            state,
                             depth,
                             else_pending,
                             serial_pending,
@@ -2131,7 +2971,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                         for else_section in else_sections {
                             height += inner(
                                 &else_section.items,
-                                config,
+                                state,
                                 depth,
                                 else_pending,
                                 serial_pending,
@@ -2143,7 +2983,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                         height += block_header_space(config, depth);
                         height += inner(
                             items,
-                            config,
+                            state,
                             depth + 1,
                             else_pending,
                             serial_pending,
@@ -2156,7 +2996,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                             height += block_else_before(config, depth) + block_else_after(config, depth);
                             height += inner(
                                 &else_section.items,
-                                config,
+                                state,
                                 depth + 1,
                                 else_pending,
                                 serial_pending,
@@ -2180,7 +3020,11 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
                 Item::Destroy { .. } => {
                     height += config.row_height;
                 }
+                Item::Create { .. } => {
+                    height += create_y_advance(config);
+                }
                 Item::ParticipantDecl { .. } => {}
+                Item::ParticipantBox { .. } => {} // Drawn behind the lifelines
                 Item::Autonumber { .. } => {}
                 Item::DiagramOption { .. } => {} // Options don't take space
             }
@@ -2194,7 +3038,7 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
     let mut parallel_depth = 0;
     inner(
         items,
-        config,
+        state,
         depth,
         &mut else_pending,
         &mut serial_pending,
@@ -2205,16 +3049,25 @@ fn calculate_height(items: &[Item], config: &Config, depth: usize) -> f64 {
 
 /// Pre-calculate destroyed participants and their Y positions
 /// This is needed because lifelines are drawn before render_items processes Item::Destroy
-fn collect_destroyed(items: &[Item], config: &Config, start_y: f64) -> HashMap<String, f64> {
+fn collect_destroyed(items: &[Item], state: &RenderState, start_y: f64) -> HashMap<String, f64> {
     fn inner(
         items: &[Item],
-        config: &Config,
+        state: &RenderState,
         current_y: &mut f64,
         destroyed: &mut HashMap<String, f64>,
     ) {
+        let config = &state.config;
         for item in items {
             match item {
-                Item::Message { from, to, text, arrow, .. } => {
+                Item::Message {
+                    from,
+                    to,
+                    text,
+                    arrow,
+                    create,
+                    note,
+                    ..
+                } => {
                     let is_self = from == to;
                     let line_count = text.split("\\n").count();
                     let delay_offset = arrow.delay.map(|d| d as f64 * DELAY_UNIT).unwrap_or(0.0);
@@ -2223,6 +3076,13 @@ fn collect_destroyed(items: &[Item], config: &Config, start_y: f64) -> HashMap<S
                     } else {
                         *current_y += regular_message_y_advance(config, line_count, delay_offset);
                     }
+                    *current_y += link_note_y_extra(config, note.as_deref(), is_self, line_count, delay_offset);
+                    if *create {
+                        *current_y += config.row_height;
+                    }
+                }
+                Item::Create { .. } => {
+                    *current_y += create_y_advance(config);
                 }
                 Item::Note { text, .. } => {
                     let line_count = text.split("\\n").count();
@@ -2239,230 +3099,434 @@ fn collect_destroyed(items: &[Item], config: &Config, start_y: f64) -> HashMap<S
                     let line_count = text.split("\\n").count();
                     *current_y += description_y_advance(config, line_count);
                 }
-                Item::Destroy { participant } => {
+                Item::Divider { .. } => {
+                    *current_y += divider_y_advance(config);
+                }
+                Item::Spacer { height, .. } => {
+                    *current_y += spacer_y_advance(config, *height);
+                }
+                Item::Delay { .. } => {
+                    *current_y += delay_y_advance(config);
+                }
+                Item::Destroy { participant, .. } => {
                     // destroy_y is at the previous message position
                     let destroy_y = *current_y - config.row_height;
                     destroyed.insert(participant.clone(), destroy_y);
                     *current_y += config.row_height;
                 }
+                Item::Block {
+                    kind: kind @ BlockKind::Row,
+                    items,
+                    ..
+                } => {
+                    // Every message of a row starts at the same y
+                    let start_y = *current_y;
+                    let mut end_y = start_y;
+                    for item in items {
+                        let mut y = start_y;
+                        inner(std::slice::from_ref(item), state, &mut y, destroyed);
+                        end_y = end_y.max(y);
+                    }
+                    *current_y = end_y + message_row_label_room(state, kind, items);
+                }
                 Item::Block { items, else_sections, kind, .. } => {
                     if block_has_frame(kind) {
                         *current_y += block_header_space(config, 0);
                     }
-                    inner(items, config, current_y, destroyed);
+                    inner(items, state, current_y, destroyed);
                     for else_section in else_sections {
                         if block_has_frame(kind) {
                             *current_y += block_else_before(config, 0) + block_else_after(config, 0);
                         }
-                        inner(&else_section.items, config, current_y, destroyed);
+                        inner(&else_section.items, state, current_y, destroyed);
                     }
                     if block_has_frame(kind) {
                         *current_y += block_end_y_advance(config, 0);
                     }
                 }
-                Item::Activate { .. } | Item::Deactivate { .. } | Item::Autonumber { .. } | Item::ParticipantDecl { .. } | Item::DiagramOption { .. } => {}
+                Item::Activate { .. } | Item::Deactivate { .. } | Item::Autonumber { .. } | Item::ParticipantDecl { .. } | Item::ParticipantBox { .. } | Item::DiagramOption { .. } => {}
             }
         }
     }
 
     let mut destroyed = HashMap::new();
     let mut current_y = start_y;
-    inner(items, config, &mut current_y, &mut destroyed);
+    inner(items, state, &mut current_y, &mut destroyed);
     destroyed
 }
 
-fn render_participant_headers(svg: &mut String, state: &RenderState, y: f64) {
-    let shape = state.config.theme.participant_shape;
+/// Inline `style` attributes for a participant's style overrides
+///
+/// Each is empty when nothing is overridden, so the theme classes apply.
+struct ParticipantStyleAttrs {
+    /// Fill and stroke for boxes, shapes and icon heads
+    shape_style: String,
+    /// Stroke for figure lines
+    line_style: String,
+    /// Fill for the name text
+    text_style: String,
+}
+
+fn participant_style_attrs(style: &ParticipantStyle) -> ParticipantStyleAttrs {
+    fn attr(props: &[(&str, &Option<String>)]) -> String {
+        let css: Vec<String> = props
+            .iter()
+            .filter_map(|(prop, value)| {
+                value
+                    .as_deref()
+                    .filter(|v| is_plain_color(v))
+                    .map(|v| format!("{}: {}", prop, escape_xml(&css_color(v))))
+            })
+            .collect();
+        if css.is_empty() {
+            String::new()
+        } else {
+            format!(r#" style="{}""#, css.join("; "))
+        }
+    }
+    ParticipantStyleAttrs {
+        shape_style: attr(&[("fill", &style.fill), ("stroke", &style.stroke)]),
+        line_style: attr(&[("stroke", &style.stroke)]),
+        text_style: attr(&[("fill", &style.text)]),
+    }
+}
+
+/// Inline `style` attributes for a message's style overrides
+///
+/// Each is empty when nothing is overridden, so the theme classes apply.
+struct MessageStyleAttrs {
+    /// Stroke color and width for the line
+    line_style: String,
+    /// Fill for filled arrowheads
+    head_fill_style: String,
+    /// Stroke for open and cross arrowheads
+    head_stroke_style: String,
+    /// Fill for the label text
+    text_style: String,
+}
+
+fn message_style_attrs(style: &MessageStyle) -> MessageStyleAttrs {
+    let color = style.color.as_deref().filter(|c| is_plain_color(c)).map(|c| escape_xml(&css_color(c)));
+    let text = style.text.as_deref().filter(|c| is_plain_color(c)).map(|c| escape_xml(&css_color(c)));
+    let mut line = Vec::new();
+    if let Some(color) = &color {
+        line.push(format!("stroke: {}", color));
+    }
+    if let Some(width) = style.width {
+        line.push(format!("stroke-width: {}", width));
+    }
+    MessageStyleAttrs {
+        line_style: style_attr(&line),
+        head_fill_style: style_attr(&color.iter().map(|c| format!("fill: {}", c)).collect::<Vec<_>>()),
+        head_stroke_style: style_attr(&color.iter().map(|c| format!("stroke: {}", c)).collect::<Vec<_>>()),
+        text_style: style_attr(&text.iter().map(|c| format!("fill: {}", c)).collect::<Vec<_>>()),
+    }
+}
+
+/// ` style="..."` from CSS declarations, or nothing when there are none
+fn style_attr(css: &[String]) -> String {
+    if css.is_empty() {
+        String::new()
+    } else {
+        format!(r#" style="{}""#, css.join("; "))
+    }
+}
+
+/// Width of an autonumber badge: a circle for short numbers, a pill for longer ones
+fn autonumber_badge_width(label: &str) -> f64 {
+    (estimate_message_width(label, AUTONUMBER_FONT_SIZE) + AUTONUMBER_BADGE_PADDING * 2.0).max(AUTONUMBER_BADGE_HEIGHT)
+}
+
+/// Draw an autonumber badge centered on the line at `y`, beside the arrow start `x`
+fn render_autonumber_badge(svg: &mut String, label: &str, x: f64, y: f64, going_right: bool) {
+    let width = autonumber_badge_width(label);
+    let left = if going_right {
+        x + AUTONUMBER_BADGE_GAP
+    } else {
+        x - AUTONUMBER_BADGE_GAP - width
+    };
+    writeln!(
+        svg,
+        r#"  <rect x="{x}" y="{y}" width="{w}" height="{h}" rx="{r}" class="autonumber-badge"/>"#,
+        x = left,
+        y = y - AUTONUMBER_BADGE_HEIGHT / 2.0,
+        w = width,
+        h = AUTONUMBER_BADGE_HEIGHT,
+        r = AUTONUMBER_BADGE_HEIGHT / 2.0
+    )
+    .unwrap();
+    writeln!(
+        svg,
+        r#"  <text x="{x}" y="{y}" class="autonumber-text" text-anchor="middle" dominant-baseline="central">{t}</text>"#,
+        x = left + width / 2.0,
+        y = y,
+        t = escape_xml(label)
+    )
+    .unwrap();
+}
+
+/// Draw an arrowhead with its tip at (x, y), pointing in `direction` (radians)
+fn render_arrowhead(svg: &mut String, head: ArrowHead, x: f64, y: f64, direction: f64, attrs: &MessageStyleAttrs) {
+    let MessageStyleAttrs {
+        head_fill_style: fill_style,
+        head_stroke_style: stroke_style,
+        ..
+    } = attrs;
+    match head {
+        ArrowHead::Filled => {
+            let points = arrowhead_points(x, y, direction);
+            writeln!(svg, r#"  <polygon points="{points}" class="arrowhead"{fill_style}/>"#).unwrap();
+        }
+        ArrowHead::Open => {
+            let points = arrowhead_points(x, y, direction);
+            writeln!(svg, r#"  <polyline points="{points}" class="arrowhead-open"{stroke_style}/>"#).unwrap();
+        }
+        ArrowHead::HalfUpper | ArrowHead::HalfLower => {
+            let points = half_arrowhead_points(x, y, direction, head == ArrowHead::HalfUpper);
+            writeln!(svg, r#"  <polygon points="{points}" class="arrowhead"{fill_style}/>"#).unwrap();
+        }
+        ArrowHead::Cross => {
+            // Centered just inside the endpoint so it doesn't cross the lifeline
+            let cx = x - ARROW_CROSS_HALF_SIZE * direction.cos();
+            let cy = y - ARROW_CROSS_HALF_SIZE * direction.sin();
+            let d = ARROW_CROSS_HALF_SIZE;
+            writeln!(
+                svg,
+                r#"  <path d="M {x1} {y1} L {x2} {y2} M {x1} {y2} L {x2} {y1}" class="arrowhead-cross"{stroke_style}/>"#,
+                x1 = cx - d,
+                y1 = cy - d,
+                x2 = cx + d,
+                y2 = cy + d
+            )
+            .unwrap();
+        }
+        ArrowHead::Circle => {
+            let cx = x - ARROW_CIRCLE_RADIUS * direction.cos();
+            let cy = y - ARROW_CIRCLE_RADIUS * direction.sin();
+            writeln!(
+                svg,
+                r#"  <circle cx="{cx}" cy="{cy}" r="{r}" class="arrowhead"{fill_style}/>"#,
+                r = ARROW_CIRCLE_RADIUS
+            )
+            .unwrap();
+        }
+    }
+}
 
+/// Render the participant headers at the top, except for participants created later
+fn render_participant_headers(svg: &mut String, state: &RenderState, y: f64) {
     for p in &state.participants {
-        let x = state.get_x(p.id());
-        let p_width = state.get_participant_width(p.id());
-        let box_x = x - p_width / 2.0;
+        if !state.created.contains_key(p.id()) {
+            render_participant_header(svg, state, p, y);
+        }
+    }
+}
 
-        match p.kind {
-            ParticipantKind::Participant => {
-                // Draw shape based on theme
-                match shape {
-                    ParticipantShape::Rectangle => {
+/// Draw the header of a participant created mid-diagram, centered on `center_y`
+fn render_created_header(svg: &mut String, state: &mut RenderState, participant: &str, center_y: f64) {
+    let top = center_y - state.config.header_height / 2.0;
+    state.created.insert(participant.to_string(), top);
+    if let Some(p) = state.participants.iter().find(|p| p.id() == participant).cloned() {
+        render_participant_header(svg, state, &p, top);
+    }
+}
+
+fn render_participant_header(svg: &mut String, state: &RenderState, p: &Participant, y: f64) {
+    let shape = state.config.theme.participant_shape;
+
+    let x = state.get_x(p.id());
+    let p_width = state.get_participant_width(p.id());
+    let box_x = x - p_width / 2.0;
+    let ParticipantStyleAttrs {
+        shape_style,
+        line_style,
+        text_style,
+    } = participant_style_attrs(&p.style);
+
+    match p.kind {
+        ParticipantKind::Participant => {
+            // Draw shape based on theme
+            match shape {
+                ParticipantShape::Rectangle => {
+                    writeln!(
+                        svg,
+                        r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="participant"{shape_style}/>"#,
+                        x = box_x,
+                        y = y,
+                        w = p_width,
+                        h = state.config.header_height
+                    )
+                    .unwrap();
+                }
+                ParticipantShape::RoundedRect => {
+                    writeln!(
+                        svg,
+                        r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" rx="8" ry="8" class="participant"{shape_style}/>"#,
+                        x = box_x,
+                        y = y,
+                        w = p_width,
+                        h = state.config.header_height
+                    )
+                    .unwrap();
+                }
+                ParticipantShape::Circle => {
+                    // Draw ellipse/circle that fits in the header area
+                    let rx = p_width / 2.0 - 5.0;
+                    let ry = state.config.header_height / 2.0 - 2.0;
+                    writeln!(
+                        svg,
+                        r#"<ellipse cx="{cx}" cy="{cy}" rx="{rx}" ry="{ry}" class="participant"{shape_style}/>"#,
+                        cx = x,
+                        cy = y + state.config.header_height / 2.0,
+                        rx = rx,
+                        ry = ry
+                    )
+                    .unwrap();
+                }
+            }
+            // Name centered in box (handle multiline with \n)
+            let lines: Vec<&str> = p.name.split("\\n").collect();
+            if lines.len() == 1 {
+                writeln!(
+                    svg,
+                    r#"<text x="{x}" y="{y}" class="participant-text"{text_style}>{name}</text>"#,
+                    x = x,
+                    y = y + state.config.header_height / 2.0,
+                    name = escape_xml(&p.name)
+                )
+                .unwrap();
+            } else {
+                let line_height = state.config.font_size + 2.0;
+                let total_height = lines.len() as f64 * line_height;
+                let start_y = y + state.config.header_height / 2.0 - total_height / 2.0
+                    + line_height * 0.8;
+                write!(svg, r#"<text x="{x}" class="participant-text"{text_style}>"#, x = x).unwrap();
+                for (i, line) in lines.iter().enumerate() {
+                    let dy = if i == 0 { start_y } else { line_height };
+                    if i == 0 {
                         writeln!(
                             svg,
-                            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="participant"/>"#,
-                            x = box_x,
-                            y = y,
-                            w = p_width,
-                            h = state.config.header_height
+                            r#"<tspan x="{x}" y="{y}">{text}</tspan>"#,
+                            x = x,
+                            y = dy,
+                            text = escape_xml(line)
                         )
                         .unwrap();
-                    }
-                    ParticipantShape::RoundedRect => {
-                        writeln!(
-                            svg,
-                            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" rx="8" ry="8" class="participant"/>"#,
-                            x = box_x,
-                            y = y,
-                            w = p_width,
-                            h = state.config.header_height
-                        )
-                        .unwrap();
-                    }
-                    ParticipantShape::Circle => {
-                        // Draw ellipse/circle that fits in the header area
-                        let rx = p_width / 2.0 - 5.0;
-                        let ry = state.config.header_height / 2.0 - 2.0;
+                    } else {
                         writeln!(
                             svg,
-                            r#"<ellipse cx="{cx}" cy="{cy}" rx="{rx}" ry="{ry}" class="participant"/>"#,
-                            cx = x,
-                            cy = y + state.config.header_height / 2.0,
-                            rx = rx,
-                            ry = ry
+                            r#"<tspan x="{x}" dy="{dy}">{text}</tspan>"#,
+                            x = x,
+                            dy = dy,
+                            text = escape_xml(line)
                         )
                         .unwrap();
                     }
                 }
-                // Name centered in box (handle multiline with \n)
-                let lines: Vec<&str> = p.name.split("\\n").collect();
-                if lines.len() == 1 {
-                    writeln!(
-                        svg,
-                        r#"<text x="{x}" y="{y}" class="participant-text">{name}</text>"#,
-                        x = x,
-                        y = y + state.config.header_height / 2.0,
-                        name = escape_xml(&p.name)
-                    )
-                    .unwrap();
-                } else {
-                    let line_height = state.config.font_size + 2.0;
-                    let total_height = lines.len() as f64 * line_height;
-                    let start_y = y + state.config.header_height / 2.0 - total_height / 2.0
-                        + line_height * 0.8;
-                    write!(svg, r#"<text x="{x}" class="participant-text">"#, x = x).unwrap();
-                    for (i, line) in lines.iter().enumerate() {
-                        let dy = if i == 0 { start_y } else { line_height };
-                        if i == 0 {
-                            writeln!(
-                                svg,
-                                r#"<tspan x="{x}" y="{y}">{text}</tspan>"#,
-                                x = x,
-                                y = dy,
-                                text = escape_xml(line)
-                            )
-                            .unwrap();
-                        } else {
-                            writeln!(
-                                svg,
-                                r#"<tspan x="{x}" dy="{dy}">{text}</tspan>"#,
-                                x = x,
-                                dy = dy,
-                                text = escape_xml(line)
-                            )
-                            .unwrap();
-                        }
-                    }
-                    writeln!(svg, "</text>").unwrap();
-                }
+                writeln!(svg, "</text>").unwrap();
             }
-            ParticipantKind::Actor => {
-                // Stick figure at top of header area, name below within header
-                let head_r = 8.0;
-                let body_len = 12.0;
-                let arm_len = 10.0;
-                let leg_len = 10.0;
-                let figure_height = 38.0; // head(16) + body(12) + legs(10)
-
-                // Position figure at top with small margin
-                let fig_top = y + 8.0;
-                let fig_center_y = fig_top + head_r + body_len / 2.0;
-                let arm_y = fig_center_y + 2.0;
-
-                // Head
-                writeln!(
-                    svg,
-                    r#"<circle cx="{x}" cy="{cy}" r="{r}" class="actor-head"/>"#,
-                    x = x,
-                    cy = fig_center_y - body_len / 2.0 - head_r,
-                    r = head_r
-                )
-                .unwrap();
-                // Body
-                writeln!(
-                    svg,
-                    r#"<line x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" class="actor-body"/>"#,
-                    x = x,
-                    y1 = fig_center_y - body_len / 2.0,
-                    y2 = fig_center_y + body_len / 2.0
-                )
-                .unwrap();
-                // Arms
-                writeln!(
-                    svg,
-                    r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" class="actor-body"/>"#,
-                    x1 = x - arm_len,
-                    y = arm_y,
-                    x2 = x + arm_len
-                )
-                .unwrap();
-                // Left leg
-                writeln!(
-                    svg,
-                    r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"/>"#,
-                    x = x,
-                    y1 = fig_center_y + body_len / 2.0,
-                    x2 = x - leg_len * 0.6,
-                    y2 = fig_center_y + body_len / 2.0 + leg_len
-                )
-                .unwrap();
-                // Right leg
+        }
+        ParticipantKind::Actor => {
+            // Stick figure at top of header area, name below within header
+            let head_r = 8.0;
+            let body_len = 12.0;
+            let arm_len = 10.0;
+            let leg_len = 10.0;
+            let figure_height = 38.0; // head(16) + body(12) + legs(10)
+
+            // Position figure at top with small margin
+            let fig_top = y + 8.0;
+            let fig_center_y = fig_top + head_r + body_len / 2.0;
+            let arm_y = fig_center_y + 2.0;
+
+            // Head
+            writeln!(
+                svg,
+                r#"<circle cx="{x}" cy="{cy}" r="{r}" class="actor-head"{shape_style}/>"#,
+                x = x,
+                cy = fig_center_y - body_len / 2.0 - head_r,
+                r = head_r
+            )
+            .unwrap();
+            // Body
+            writeln!(
+                svg,
+                r#"<line x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" class="actor-body"{line_style}/>"#,
+                x = x,
+                y1 = fig_center_y - body_len / 2.0,
+                y2 = fig_center_y + body_len / 2.0
+            )
+            .unwrap();
+            // Arms
+            writeln!(
+                svg,
+                r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" class="actor-body"{line_style}/>"#,
+                x1 = x - arm_len,
+                y = arm_y,
+                x2 = x + arm_len
+            )
+            .unwrap();
+            // Left leg
+            writeln!(
+                svg,
+                r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"{line_style}/>"#,
+                x = x,
+                y1 = fig_center_y + body_len / 2.0,
+                x2 = x - leg_len * 0.6,
+                y2 = fig_center_y + body_len / 2.0 + leg_len
+            )
+            .unwrap();
+            // Right leg
+            writeln!(
+                svg,
+                r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"{line_style}/>"#,
+                x = x,
+                y1 = fig_center_y + body_len / 2.0,
+                x2 = x + leg_len * 0.6,
+                y2 = fig_center_y + body_len / 2.0 + leg_len
+            )
+            .unwrap();
+            // Name below figure (within header)
+            let name_lines: Vec<&str> = p.name.split("\\n").collect();
+            let name_start_y = fig_top + figure_height + 5.0;
+            if name_lines.len() == 1 {
                 writeln!(
                     svg,
-                    r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"/>"#,
+                    r#"<text x="{x}" y="{y}" class="participant-text"{text_style}>{name}</text>"#,
                     x = x,
-                    y1 = fig_center_y + body_len / 2.0,
-                    x2 = x + leg_len * 0.6,
-                    y2 = fig_center_y + body_len / 2.0 + leg_len
+                    y = name_start_y + state.config.font_size,
+                    name = escape_xml(&p.name)
                 )
                 .unwrap();
-                // Name below figure (within header)
-                let name_lines: Vec<&str> = p.name.split("\\n").collect();
-                let name_start_y = fig_top + figure_height + 5.0;
-                if name_lines.len() == 1 {
-                    writeln!(
-                        svg,
-                        r#"<text x="{x}" y="{y}" class="participant-text">{name}</text>"#,
-                        x = x,
-                        y = name_start_y + state.config.font_size,
-                        name = escape_xml(&p.name)
-                    )
-                    .unwrap();
-                } else {
-                    // Multiline actor name using tspan
-                    let line_height = state.config.font_size + 2.0;
-                    writeln!(svg, r#"<text x="{x}" class="participant-text">"#, x = x).unwrap();
-                    for (i, line) in name_lines.iter().enumerate() {
-                        if i == 0 {
-                            writeln!(
-                                svg,
-                                r#"<tspan x="{x}" y="{y}">{text}</tspan>"#,
-                                x = x,
-                                y = name_start_y + state.config.font_size,
-                                text = escape_xml(line)
-                            )
-                            .unwrap();
-                        } else {
-                            writeln!(
-                                svg,
-                                r#"<tspan x="{x}" dy="{dy}">{text}</tspan>"#,
-                                x = x,
-                                dy = line_height,
-                                text = escape_xml(line)
-                            )
-                            .unwrap();
-                        }
+            } else {
+                // Multiline actor name using tspan
+                let line_height = state.config.font_size + 2.0;
+                writeln!(svg, r#"<text x="{x}" class="participant-text"{text_style}>"#, x = x).unwrap();
+                for (i, line) in name_lines.iter().enumerate() {
+                    if i == 0 {
+                        writeln!(
+                            svg,
+                            r#"<tspan x="{x}" y="{y}">{text}</tspan>"#,
+                            x = x,
+                            y = name_start_y + state.config.font_size,
+                            text = escape_xml(line)
+                        )
+                        .unwrap();
+                    } else {
+                        writeln!(
+                            svg,
+                            r#"<tspan x="{x}" dy="{dy}">{text}</tspan>"#,
+                            x = x,
+                            dy = line_height,
+                            text = escape_xml(line)
+                        )
+                        .unwrap();
                     }
-                    writeln!(svg, "</text>").unwrap();
                 }
+                writeln!(svg, "</text>").unwrap();
             }
         }
+        _ => render_participant_glyph(svg, state, p, x, y),
     }
 }
 
@@ -2479,6 +3543,11 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
         let x = state.get_x(p.id());
         let p_width = state.get_participant_width(p.id());
         let box_x = x - p_width / 2.0;
+        let ParticipantStyleAttrs {
+            shape_style,
+            line_style,
+            text_style,
+        } = participant_style_attrs(&p.style);
 
         match p.kind {
             ParticipantKind::Participant => {
@@ -2487,7 +3556,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                     ParticipantShape::Rectangle => {
                         writeln!(
                             svg,
-                            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="participant"/>"#,
+                            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="participant"{shape_style}/>"#,
                             x = box_x,
                             y = y,
                             w = p_width,
@@ -2498,7 +3567,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                     ParticipantShape::RoundedRect => {
                         writeln!(
                             svg,
-                            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" rx="8" ry="8" class="participant"/>"#,
+                            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" rx="8" ry="8" class="participant"{shape_style}/>"#,
                             x = box_x,
                             y = y,
                             w = p_width,
@@ -2510,7 +3579,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                         let r = state.config.header_height / 2.0;
                         writeln!(
                             svg,
-                            r#"<ellipse cx="{cx}" cy="{cy}" rx="{rx}" ry="{ry}" class="participant"/>"#,
+                            r#"<ellipse cx="{cx}" cy="{cy}" rx="{rx}" ry="{ry}" class="participant"{shape_style}/>"#,
                             cx = x,
                             cy = y + r,
                             rx = p_width / 2.0,
@@ -2528,14 +3597,14 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                 if lines.len() == 1 {
                     writeln!(
                         svg,
-                        r#"<text x="{x}" y="{y}" class="participant-text">{name}</text>"#,
+                        r#"<text x="{x}" y="{y}" class="participant-text"{text_style}>{name}</text>"#,
                         x = x,
                         y = text_start_y,
                         name = escape_xml(&p.name)
                     )
                     .unwrap();
                 } else {
-                    writeln!(svg, r#"<text x="{x}" class="participant-text">"#, x = x).unwrap();
+                    writeln!(svg, r#"<text x="{x}" class="participant-text"{text_style}>"#, x = x).unwrap();
                     for (i, line) in lines.iter().enumerate() {
                         if i == 0 {
                             writeln!(
@@ -2574,7 +3643,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                 // Head
                 writeln!(
                     svg,
-                    r#"<circle cx="{x}" cy="{cy}" r="{r}" class="actor-head"/>"#,
+                    r#"<circle cx="{x}" cy="{cy}" r="{r}" class="actor-head"{shape_style}/>"#,
                     x = x,
                     cy = fig_center_y - body_len / 2.0 - head_r,
                     r = head_r
@@ -2583,7 +3652,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                 // Body
                 writeln!(
                     svg,
-                    r#"<line x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" class="actor-body"/>"#,
+                    r#"<line x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" class="actor-body"{line_style}/>"#,
                     x = x,
                     y1 = fig_center_y - body_len / 2.0,
                     y2 = fig_center_y + body_len / 2.0
@@ -2592,7 +3661,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                 // Arms
                 writeln!(
                     svg,
-                    r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" class="actor-body"/>"#,
+                    r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" class="actor-body"{line_style}/>"#,
                     x1 = x - arm_len,
                     y = arm_y,
                     x2 = x + arm_len
@@ -2601,7 +3670,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                 // Left leg
                 writeln!(
                     svg,
-                    r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"/>"#,
+                    r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"{line_style}/>"#,
                     x = x,
                     y1 = fig_center_y + body_len / 2.0,
                     x2 = x - leg_len * 0.6,
@@ -2611,7 +3680,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                 // Right leg
                 writeln!(
                     svg,
-                    r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"/>"#,
+                    r#"<line x1="{x}" y1="{y1}" x2="{x2}" y2="{y2}" class="actor-body"{line_style}/>"#,
                     x = x,
                     y1 = fig_center_y + body_len / 2.0,
                     x2 = x + leg_len * 0.6,
@@ -2624,7 +3693,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                 if name_lines.len() == 1 {
                     writeln!(
                         svg,
-                        r#"<text x="{x}" y="{y}" class="participant-text">{name}</text>"#,
+                        r#"<text x="{x}" y="{y}" class="participant-text"{text_style}>{name}</text>"#,
                         x = x,
                         y = name_start_y + state.config.font_size,
                         name = escape_xml(&p.name)
@@ -2632,7 +3701,7 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                     .unwrap();
                 } else {
                     let line_height = state.config.font_size + 2.0;
-                    writeln!(svg, r#"<text x="{x}" class="participant-text">"#, x = x).unwrap();
+                    writeln!(svg, r#"<text x="{x}" class="participant-text"{text_style}>"#, x = x).unwrap();
                     for (i, line) in name_lines.iter().enumerate() {
                         if i == 0 {
                             writeln!(
@@ -2657,10 +3726,188 @@ fn render_participant_footers(svg: &mut String, state: &RenderState, y: f64) {
                     writeln!(svg, "</text>").unwrap();
                 }
             }
+            _ => render_participant_glyph(svg, state, p, x, y),
         }
     }
 }
 
+/// Render a database, queue, collections, boundary, control or entity
+/// participant in the header area starting at `y` (shared by headers and footers)
+fn render_participant_glyph(svg: &mut String, state: &RenderState, p: &Participant, x: f64, y: f64) {
+    let p_width = state.get_participant_width(p.id());
+    let height = state.config.header_height;
+    let ParticipantStyleAttrs {
+        shape_style,
+        line_style,
+        text_style,
+    } = participant_style_attrs(&p.style);
+    let left = x - p_width / 2.0;
+    let right = x + p_width / 2.0;
+
+    match p.kind {
+        ParticipantKind::Database => {
+            let cap = DATABASE_CAP_HEIGHT;
+            let rx = p_width / 2.0;
+            writeln!(
+                svg,
+                r#"<path d="M {l} {t} A {rx} {cap} 0 0 0 {r} {t} L {r} {b} A {rx} {cap} 0 0 1 {l} {b} Z" class="participant"{shape_style}/>"#,
+                l = left,
+                r = right,
+                t = y + cap,
+                b = y + height - cap,
+                rx = rx,
+                cap = cap
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                r#"<ellipse cx="{cx}" cy="{cy}" rx="{rx}" ry="{ry}" class="participant"{shape_style}/>"#,
+                cx = x,
+                cy = y + cap,
+                rx = rx,
+                ry = cap
+            )
+            .unwrap();
+            render_glyph_name(svg, state, &p.name, &text_style, x, y + height / 2.0 + cap / 2.0);
+        }
+        ParticipantKind::Queue => {
+            let cap = QUEUE_CAP_WIDTH;
+            let ry = height / 2.0;
+            writeln!(
+                svg,
+                r#"<path d="M {l} {t} L {r} {t} A {cap} {ry} 0 0 1 {r} {b} L {l} {b} A {cap} {ry} 0 0 1 {l} {t} Z" class="participant"{shape_style}/>"#,
+                l = left + cap,
+                r = right - cap,
+                t = y,
+                b = y + height,
+                cap = cap,
+                ry = ry
+            )
+            .unwrap();
+            writeln!(
+                svg,
+                r#"<ellipse cx="{cx}" cy="{cy}" rx="{rx}" ry="{ry}" class="participant"{shape_style}/>"#,
+                cx = right - cap,
+                cy = y + ry,
+                rx = cap,
+                ry = ry
+            )
+            .unwrap();
+            render_glyph_name(svg, state, &p.name, &text_style, x - cap / 2.0, y + height / 2.0);
+        }
+        ParticipantKind::Collections => {
+            let offset = COLLECTIONS_OFFSET;
+            for (dx, dy) in [(offset, 0.0), (0.0, offset)] {
+                writeln!(
+                    svg,
+                    r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="participant"{shape_style}/>"#,
+                    x = left + dx,
+                    y = y + dy,
+                    w = p_width - offset,
+                    h = height - offset
+                )
+                .unwrap();
+            }
+            render_glyph_name(svg, state, &p.name, &text_style, x - offset / 2.0, y + (height + offset) / 2.0);
+        }
+        ParticipantKind::Boundary | ParticipantKind::Control | ParticipantKind::Entity => {
+            // ICONIX icons sit where the actor figure goes, with the name below
+            let r = ICON_RADIUS;
+            let cx = x;
+            let cy = y + 8.0 + r + 2.0;
+            writeln!(
+                svg,
+                r#"<circle cx="{cx}" cy="{cy}" r="{r}" class="actor-head"{shape_style}/>"#,
+                cx = cx,
+                cy = cy,
+                r = r
+            )
+            .unwrap();
+            match p.kind {
+                ParticipantKind::Boundary => {
+                    let bar_x = cx - r - 8.0;
+                    writeln!(
+                        svg,
+                        r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" class="actor-body"{line_style}/>"#,
+                        x1 = bar_x,
+                        x2 = cx - r,
+                        y = cy
+                    )
+                    .unwrap();
+                    writeln!(
+                        svg,
+                        r#"<line x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" class="actor-body"{line_style}/>"#,
+                        x = bar_x,
+                        y1 = cy - r,
+                        y2 = cy + r
+                    )
+                    .unwrap();
+                }
+                ParticipantKind::Control => {
+                    writeln!(
+                        svg,
+                        r#"<polyline points="{x1},{y1} {x2},{y2} {x1},{y3}" class="actor-body"{line_style}/>"#,
+                        x1 = cx + 4.0,
+                        y1 = cy - r - 4.0,
+                        x2 = cx - 1.0,
+                        y2 = cy - r,
+                        y3 = cy - r + 4.0
+                    )
+                    .unwrap();
+                }
+                _ => {
+                    writeln!(
+                        svg,
+                        r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" class="actor-body"{line_style}/>"#,
+                        x1 = cx - r,
+                        x2 = cx + r,
+                        y = cy + r
+                    )
+                    .unwrap();
+                }
+            }
+            // Name below the icon, at the same place as an actor's name
+            let first_line_y = y + 8.0 + 38.0 + 5.0 + state.config.font_size;
+            let line_height = state.config.font_size + 2.0;
+            let extra_lines = p.name.split("\\n").count() - 1;
+            render_glyph_name(svg, state, &p.name, &text_style, x, first_line_y + extra_lines as f64 * line_height / 2.0);
+        }
+        ParticipantKind::Participant | ParticipantKind::Actor => {}
+    }
+}
+
+/// Render a (possibly multiline) participant name vertically centered on `center_y`
+fn render_glyph_name(svg: &mut String, state: &RenderState, name: &str, text_style: &str, x: f64, center_y: f64) {
+    let lines: Vec<&str> = name.split("\\n").collect();
+    let line_height = state.config.font_size + 2.0;
+    // participant-text uses a middle baseline, so y is the center of each line
+    let first_y = center_y - (lines.len() - 1) as f64 * line_height / 2.0;
+
+    write!(svg, r#"<text x="{x}" class="participant-text"{text_style}>"#, x = x).unwrap();
+    for (i, line) in lines.iter().enumerate() {
+        if i == 0 {
+            write!(
+                svg,
+                r#"<tspan x="{x}" y="{y}">{text}</tspan>"#,
+                x = x,
+                y = first_y,
+                text = escape_xml(line)
+            )
+            .unwrap();
+        } else {
+            write!(
+                svg,
+                r#"<tspan x="{x}" dy="{dy}">{text}</tspan>"#,
+                x = x,
+                dy = line_height,
+                text = escape_xml(line)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(svg, "</text>").unwrap();
+}
+
 fn render_items(svg: &mut String, state: &mut RenderState, items: &[Item], depth: usize) {
     for item in items {
         match item {
@@ -2670,8 +3917,11 @@ fn render_items(svg: &mut String, state: &mut RenderState, items: &[Item], depth
                 text,
                 arrow,
                 activate,
+                activation_color,
                 deactivate,
                 create,
+                style,
+                note,
                 ..
             } => {
                 render_message(
@@ -2681,45 +3931,47 @@ fn render_items(svg: &mut String, state: &mut RenderState, items: &[Item], depth
                     to,
                     text,
                     arrow,
+                    style,
                     *activate,
+                    activation_color.as_deref(),
                     *deactivate,
                     *create,
+                    note.as_deref(),
                     depth,
                 );
             }
             Item::Note {
                 position,
+                shape,
                 participants,
                 text,
+                color,
+                ..
             } => {
-                render_note(svg, state, position, participants, text);
+                render_note(svg, state, position, *shape, participants, text, color.as_deref());
             }
             Item::Block {
                 kind,
                 label,
                 items,
                 else_sections,
+                ..
             } => {
                 render_block(svg, state, kind, label, items, else_sections, depth);
             }
-            Item::Activate { participant } => {
+            Item::Activate { participant, color, .. } => {
                 let y = state.current_y;
-                state
-                    .activations
-                    .entry(participant.clone())
-                    .or_default()
-                    .push((y, None));
+                state.activate(participant, y, color.as_deref());
             }
-            Item::Deactivate { participant } => {
-                if let Some(acts) = state.activations.get_mut(participant) {
-                    if let Some(act) = acts.last_mut() {
-                        if act.1.is_none() {
-                            act.1 = Some(state.current_y);
-                        }
-                    }
-                }
+            Item::Deactivate { participant, .. } => {
+                let y = state.current_y;
+                state.deactivate(participant, y);
             }
-            Item::Destroy { participant } => {
+            Item::Create { participant, .. } => {
+                render_created_header(svg, state, participant, state.current_y);
+                state.current_y += create_y_advance(&state.config);
+            }
+            Item::Destroy { participant, .. } => {
                 // X mark should be at the previous message's arrow position (WSD compatible)
                 // After a message, current_y is incremented by row_height, so we subtract it back
                 let destroy_y = state.current_y - state.config.row_height;
@@ -2728,8 +3980,8 @@ fn render_items(svg: &mut String, state: &mut RenderState, items: &[Item], depth
                 // Close all open activations for this participant at destroy_y
                 if let Some(acts) = state.activations.get_mut(participant) {
                     for act in acts.iter_mut() {
-                        if act.1.is_none() {
-                            act.1 = Some(destroy_y);
+                        if act.end_y.is_none() {
+                            act.end_y = Some(destroy_y);
                         }
                     }
                 }
@@ -2738,17 +3990,15 @@ fn render_items(svg: &mut String, state: &mut RenderState, items: &[Item], depth
                 state.destroy_marks.push((x, destroy_y));
                 state.current_y += state.config.row_height;
             }
-            Item::Autonumber { enabled, start } => {
-                if *enabled {
-                    state.autonumber = Some(start.unwrap_or(1));
-                } else {
-                    state.autonumber = None;
-                }
+            Item::Autonumber { command, .. } => {
+                state.autonumber.apply(command);
             }
-            Item::ParticipantDecl { .. } => {
+            Item::ParticipantDecl { .. } | Item::ParticipantBox { .. } => {
                 // Already processed
             }
-            Item::State { participants, text } => {
+            Item::State {
+                participants, text, ..
+            } => {
                 render_state(svg, state, participants, text);
             }
             Item::Ref {
@@ -2758,6 +4008,7 @@ fn render_items(svg: &mut String, state: &mut RenderState, items: &[Item], depth
                 input_label,
                 output_to,
                 output_label,
+                ..
             } => {
                 render_ref(
                     svg,
@@ -2773,9 +4024,18 @@ fn render_items(svg: &mut String, state: &mut RenderState, items: &[Item], depth
             Item::DiagramOption { .. } => {
                 // Options are processed at render start, not during item rendering
             }
-            Item::Description { text } => {
+            Item::Description { text, .. } => {
                 render_description(svg, state, text);
             }
+            Item::Divider { text, .. } => {
+                render_divider(svg, state, text);
+            }
+            Item::Spacer { height, .. } => {
+                state.current_y += spacer_y_advance(&state.config, *height);
+            }
+            Item::Delay { text, .. } => {
+                render_delay(svg, state, text);
+            }
         }
     }
 }
@@ -2787,9 +4047,12 @@ fn render_message(
     to: &str,
     text: &str,
     arrow: &Arrow,
+    style: &MessageStyle,
     activate: bool,
+    activation_color: Option<&str>,
     deactivate: bool,
     create: bool,
+    note: Option<&str>,
     _depth: usize,
 ) {
     // Get base lifeline positions (used for text centering and direction calculation)
@@ -2803,17 +4066,12 @@ fn render_message(
         LineStyle::Solid => "message",
         LineStyle::Dashed => "message-dashed",
     };
-    let is_filled = matches!(arrow.head, ArrowHead::Filled);
 
-    // Get autonumber prefix
-    let num_prefix = state
-        .next_number()
-        .map(|n| format!("{}. ", n))
-        .unwrap_or_default();
+    // Autonumber is drawn as a badge, so it doesn't change the label
+    let number = state.next_number();
 
     // Calculate text lines and height
-    let display_text = format!("{}{}", num_prefix, text);
-    let lines: Vec<&str> = display_text.split("\\n").collect();
+    let lines: Vec<&str> = text.split("\\n").collect();
     let line_height = state.config.font_size + 4.0;
     let extra_height = if !is_self && lines.len() > 1 {
         // Use actual line_height (not spacing_line_height) to match text rendering
@@ -2834,7 +4092,24 @@ fn render_message(
     // Calculate activation-aware arrow endpoints
     let going_right = base_x2 > base_x1;
     let x1 = state.get_arrow_start_x(from, y, going_right);
-    let x2 = state.get_arrow_end_x(to, y, !going_right);
+    let x2 = if create && !is_self {
+        // The arrow ends on the edge of the created participant's header
+        let half_width = state.get_participant_width(to) / 2.0;
+        if going_right {
+            base_x2 - half_width
+        } else {
+            base_x2 + half_width
+        }
+    } else {
+        state.get_arrow_end_x(to, y, !going_right)
+    };
+
+    let attrs = message_style_attrs(style);
+    let MessageStyleAttrs {
+        line_style,
+        text_style,
+        ..
+    } = &attrs;
 
     // Open message group
     writeln!(svg, r#"<g class="message">"#).unwrap();
@@ -2842,52 +4117,48 @@ fn render_message(
     if is_self {
         // Self message - loop back
         // For self-messages, always use right edge of activation bar (loop extends to the right)
-        // Check if already active OR if this message starts a new activation
-        let is_active = state.is_participant_active_at(from, y) || activate;
+        // Anchor on the innermost bar, or on the bar this message opens (nested if already active)
+        let anchor_depth = if activate {
+            Some(state.next_activation_depth(from))
+        } else {
+            state.activation_depth_at(from, y)
+        };
+        let is_active = anchor_depth.is_some();
         let activation_offset = if is_active {
             state.config.activation_width / 2.0
         } else {
             0.0
         };
-        let loop_anchor_x = base_x1 + activation_offset;
+        let loop_anchor_x = anchor_depth.map_or(base_x1, |depth| state.activation_center_x(from, depth) + activation_offset);
 
-        let loop_width = 40.0;
+        let loop_width = SELF_MESSAGE_LOOP_WIDTH;
         let text_block_height = lines.len() as f64 * line_height;
         // WSD: loop height equals text block height, no extra padding
         let loop_height = text_block_height.max(25.0);
         let arrow_end_x = loop_anchor_x;
         let arrow_end_y = y + loop_height;
-        // Arrowhead points left (PI radians)
+        // Both arrowheads point left (PI radians)
         let direction = std::f64::consts::PI;
-        let arrow_points = arrowhead_points(arrow_end_x, arrow_end_y, direction);
+        let start_inset = arrow.start_head.map(arrowhead_inset).unwrap_or(0.0);
 
         writeln!(
             svg,
-            r#"  <path d="M {x1} {y} L {x2} {y} L {x2} {y2} L {arrow_x} {y2}" class="{cls}"/>"#,
-            x1 = loop_anchor_x,
+            r#"  <path d="M {x1} {y} L {x2} {y} L {x2} {y2} L {arrow_x} {y2}" class="{cls}"{line_style}/>"#,
+            x1 = loop_anchor_x + start_inset,
             y = y,
             x2 = loop_anchor_x + loop_width,
             y2 = y + loop_height,
-            arrow_x = arrow_end_x + ARROWHEAD_SIZE,
+            arrow_x = arrow_end_x + arrowhead_inset(arrow.head),
             cls = line_class
         )
         .unwrap();
 
-        // Draw arrowhead as polygon or polyline
-        if is_filled {
-            writeln!(
-                svg,
-                r#"  <polygon points="{points}" class="arrowhead"/>"#,
-                points = arrow_points
-            )
-            .unwrap();
-        } else {
-            writeln!(
-                svg,
-                r#"  <polyline points="{points}" class="arrowhead-open"/>"#,
-                points = arrow_points
-            )
-            .unwrap();
+        render_arrowhead(svg, arrow.head, arrow_end_x, arrow_end_y, direction, &attrs);
+        if let Some(start_head) = arrow.start_head {
+            render_arrowhead(svg, start_head, loop_anchor_x, y, direction, &attrs);
+        }
+        if let Some(number) = &number {
+            render_autonumber_badge(svg, number, loop_anchor_x + start_inset, y, true);
         }
 
         // Text - multiline support
@@ -2899,7 +4170,7 @@ fn render_message(
             let line_y = y + 4.0 + (i as f64 + 0.5) * line_height;
             writeln!(
                 svg,
-                r#"  <text x="{x}" y="{y}" class="message-text" text-anchor="end">{t}</text>"#,
+                r#"  <text x="{x}" y="{y}" class="message-text"{text_style} text-anchor="end">{t}</text>"#,
                 x = text_x,
                 y = line_y,
                 t = escape_xml(line)
@@ -2907,6 +4178,10 @@ fn render_message(
             .unwrap();
         }
 
+        if let Some(note) = note {
+            render_link_note(svg, state, note, loop_anchor_x + loop_width + NOTE_MARGIN, y);
+        }
+
         // Close message group
         writeln!(svg, r#"</g>"#).unwrap();
 
@@ -2923,39 +4198,33 @@ fn render_message(
 
         // Calculate arrowhead direction and shorten line to not overlap with arrowhead
         let direction = arrow_direction(x1, y, x2, y2);
-        let arrow_points = arrowhead_points(x2, y2, direction);
 
-        // Shorten the line so it doesn't overlap with the arrowhead
-        let line_end_x = x2 - ARROWHEAD_SIZE * direction.cos();
-        let line_end_y = y2 - ARROWHEAD_SIZE * direction.sin();
+        // Shorten the line so it doesn't overlap with the arrowheads
+        let end_inset = arrowhead_inset(arrow.head);
+        let line_end_x = x2 - end_inset * direction.cos();
+        let line_end_y = y2 - end_inset * direction.sin();
+        let start_inset = arrow.start_head.map(arrowhead_inset).unwrap_or(0.0);
+        let line_start_x = x1 + start_inset * direction.cos();
+        let line_start_y = y + start_inset * direction.sin();
 
         // Draw arrow line (slanted if delay)
         writeln!(
             svg,
-            r#"  <line x1="{x1}" y1="{y1}" x2="{lx2}" y2="{ly2}" class="{cls}"/>"#,
-            x1 = x1,
-            y1 = y,
+            r#"  <line x1="{x1}" y1="{y1}" x2="{lx2}" y2="{ly2}" class="{cls}"{line_style}/>"#,
+            x1 = line_start_x,
+            y1 = line_start_y,
             lx2 = line_end_x,
             ly2 = line_end_y,
             cls = line_class
         )
         .unwrap();
 
-        // Draw arrowhead as polygon or polyline
-        if is_filled {
-            writeln!(
-                svg,
-                r#"  <polygon points="{points}" class="arrowhead"/>"#,
-                points = arrow_points
-            )
-            .unwrap();
-        } else {
-            writeln!(
-                svg,
-                r#"  <polyline points="{points}" class="arrowhead-open"/>"#,
-                points = arrow_points
-            )
-            .unwrap();
+        render_arrowhead(svg, arrow.head, x2, y2, direction, &attrs);
+        if let Some(start_head) = arrow.start_head {
+            render_arrowhead(svg, start_head, x1, y, direction + std::f64::consts::PI, &attrs);
+        }
+        if let Some(number) = &number {
+            render_autonumber_badge(svg, number, line_start_x, line_start_y, x2 >= x1);
         }
 
         // Text with multiline support (positioned at midpoint of slanted line)
@@ -2970,11 +4239,23 @@ fn render_message(
             let label_y_max = bottom_line_y + line_height * MESSAGE_LABEL_DESCENT_FACTOR;
             let label_x_min = text_x - max_width / 2.0;
             let label_x_max = text_x + max_width / 2.0;
-            let step = line_height * MESSAGE_LABEL_COLLISION_STEP_RATIO;
-            let raw_offset = state.reserve_message_label(label_x_min, label_x_max, label_y_min, label_y_max, step);
-            // Limit offset to prevent text from going below the arrow
-            let max_offset = y - MESSAGE_TEXT_ABOVE_ARROW - bottom_line_y;
-            raw_offset.min(max_offset.max(0.0))
+            if let Some(offset) = state.message_row_offset {
+                // On an `&` row the arrows share one y, so a colliding label moves below
+                // its arrow, into the room the row leaves for it
+                state.message_label_boxes.push(LabelBox {
+                    x_min: label_x_min,
+                    x_max: label_x_max,
+                    y_min: label_y_min + offset,
+                    y_max: label_y_max + offset,
+                });
+                offset
+            } else {
+                let step = line_height * MESSAGE_LABEL_COLLISION_STEP_RATIO;
+                let raw_offset = state.reserve_message_label(label_x_min, label_x_max, label_y_min, label_y_max, step);
+                // Limit offset to prevent text from going below the arrow
+                let max_offset = y - MESSAGE_TEXT_ABOVE_ARROW - bottom_line_y;
+                raw_offset.min(max_offset.max(0.0))
+            }
         } else {
             0.0
         };
@@ -2996,7 +4277,7 @@ fn render_message(
                 // Apply rotation transform for delayed messages
                 writeln!(
                     svg,
-                    r#"  <text x="{x}" y="{y}" class="message-text" text-anchor="middle" transform="rotate({rot},{cx},{cy})">{t}</text>"#,
+                    r#"  <text x="{x}" y="{y}" class="message-text"{text_style} text-anchor="middle" transform="rotate({rot},{cx},{cy})">{t}</text>"#,
                     x = text_x,
                     y = line_y,
                     rot = rotation,
@@ -3008,7 +4289,7 @@ fn render_message(
             } else {
                 writeln!(
                     svg,
-                    r#"  <text x="{x}" y="{y}" class="message-text" text-anchor="middle">{t}</text>"#,
+                    r#"  <text x="{x}" y="{y}" class="message-text"{text_style} text-anchor="middle">{t}</text>"#,
                     x = text_x,
                     y = line_y,
                     t = escape_xml(line)
@@ -3017,13 +4298,25 @@ fn render_message(
             }
         }
 
+        if let Some(note) = note {
+            let note_width = calculate_note_width(note, NoteShape::Folded, &state.config);
+            render_link_note(svg, state, note, text_x - note_width / 2.0, y2 + LINK_NOTE_GAP);
+        }
+
         // Close message group
         writeln!(svg, r#"</g>"#).unwrap();
 
+        if create {
+            render_created_header(svg, state, to, y2);
+        }
+
         // Add row_height plus delay offset
         state.current_y += state.config.row_height + delay_offset;
     }
 
+    let delay_offset = arrow.delay.map(|d| d as f64 * DELAY_UNIT).unwrap_or(0.0);
+    state.current_y += link_note_y_extra(&state.config, note, is_self, lines.len(), delay_offset);
+
     if create {
         state.current_y += state.config.row_height;
     }
@@ -3032,20 +4325,10 @@ fn render_message(
 
     // Handle activation
     if activate {
-        state
-            .activations
-            .entry(to.to_string())
-            .or_default()
-            .push((y, None));
+        state.activate(to, y, activation_color);
     }
     if deactivate {
-        if let Some(acts) = state.activations.get_mut(from) {
-            if let Some(act) = acts.last_mut() {
-                if act.1.is_none() {
-                    act.1 = Some(y);
-                }
-            }
-        }
+        state.deactivate(from, y);
     }
 }
 
@@ -3053,94 +4336,68 @@ fn render_note(
     svg: &mut String,
     state: &mut RenderState,
     position: &NotePosition,
+    shape: NoteShape,
     participants: &[String],
     text: &str,
+    color: Option<&str>,
 ) {
     let lines: Vec<&str> = text.split("\\n").collect();
     let line_height = note_line_height(&state.config);
 
     // Calculate note size (same padding on all sides)
     // Use estimate_text_width to properly handle CJK characters
-    let note_font_size = NOTE_LINE_HEIGHT - 4.0; // 13px
-    let text_width = estimate_text_width(text, note_font_size);
-    let content_width = (ELEMENT_PADDING * 2.0 + text_width).max(NOTE_MIN_WIDTH);
+    let content_width = calculate_note_width(text, shape, &state.config);
     let note_height = ELEMENT_PADDING * 2.0 + lines.len() as f64 * line_height;
 
-    let (x, note_width, text_anchor) = match position {
-        NotePosition::Left => {
+    let spanned = note_span_x(position, participants, &state.participants, &state.participant_x);
+    let (x, note_width) = match (position, spanned) {
+        (_, Some((left_x, right_x))) => {
+            // Span multiple participants
+            let span_width = (right_x - left_x) + NOTE_MARGIN * 2.0;
+            let w = span_width.max(content_width);
+            let x = (left_x - NOTE_MARGIN).max(state.config.padding);
+            (x, w)
+        }
+        (NotePosition::Left, None) => {
             let px = state.get_x(&participants[0]);
             // Note right edge = px - NOTE_MARGIN
             let x = (px - NOTE_MARGIN - content_width).max(state.config.padding);
-            (x, content_width, "start")
+            (x, content_width)
         }
-        NotePosition::Right => {
+        (NotePosition::Right, None) => {
             let px = state.get_x(&participants[0]);
             // Note left edge = px + NOTE_MARGIN
-            (px + NOTE_MARGIN, content_width, "start")
-        }
-        NotePosition::Over => {
-            if participants.len() == 1 {
-                let px = state.get_x(&participants[0]);
-                // Center on lifeline
-                let x = (px - content_width / 2.0).max(state.config.padding);
-                (x, content_width, "middle")
-            } else {
-                // Span multiple participants
-                // Use min/max to handle any order of participants in the syntax
-                let x1 = state.get_x(&participants[0]);
-                let x2 = state.get_x(participants.last().unwrap());
-                let left_x = x1.min(x2);
-                let right_x = x1.max(x2);
-                let span_width = (right_x - left_x) + NOTE_MARGIN * 2.0;
-                let w = span_width.max(content_width);
-                let x = (left_x - NOTE_MARGIN).max(state.config.padding);
-                (x, w, "middle")
-            }
+            (px + NOTE_MARGIN, content_width)
+        }
+        (NotePosition::Over | NotePosition::Across, None) => {
+            // Center on lifeline (`note across` with a single participant too)
+            let px = match participants.first() {
+                Some(p) => state.get_x(p),
+                None => state
+                    .participants
+                    .first()
+                    .map_or(state.total_width / 2.0, |p| state.get_x(p.id())),
+            };
+            let x = (px - content_width / 2.0).max(state.config.padding);
+            (x, content_width)
         }
     };
 
     let y = state.current_y;
-    let fold_size = NOTE_FOLD_SIZE;
-
-    // Note background with dog-ear (folded corner) effect
-    // Path: start at top-left, go right (leaving space for fold), diagonal fold, down, left, up
-    let note_path = format!(
-        "M {x} {y} L {x2} {y} L {x3} {y2} L {x3} {y3} L {x} {y3} Z",
-        x = x,
-        y = y,
-        x2 = x + note_width - fold_size,
-        x3 = x + note_width,
-        y2 = y + fold_size,
-        y3 = y + note_height
-    );
+    let fill_style = style_attr(&color.iter().map(|c| format!("fill: {}", escape_xml(&css_color(c)))).collect::<Vec<_>>());
 
-    writeln!(svg, r#"<path d="{path}" class="note"/>"#, path = note_path).unwrap();
-
-    // Draw the fold triangle (represents the folded corner)
-    let theme = &state.config.theme;
-    // Triangle: from fold start, to diagonal corner, to bottom of fold
-    let fold_path = format!(
-        "M {x1} {y1} L {x2} {y2} L {x1} {y2} Z",
-        x1 = x + note_width - fold_size,
-        y1 = y,
-        x2 = x + note_width,
-        y2 = y + fold_size
-    );
-
-    writeln!(
-        svg,
-        r##"<path d="{path}" fill="none" stroke="{stroke}" stroke-width="1"/>"##,
-        path = fold_path,
-        stroke = theme.note_stroke
-    )
-    .unwrap();
+    render_note_box(svg, &state.config.theme, shape, x, y, note_width, note_height, &fill_style);
 
     // Text position (same padding on all sides)
-    let text_x = match text_anchor {
-        "middle" => x + note_width / 2.0,
-        _ => x + ELEMENT_PADDING,
+    let centered = matches!(position, NotePosition::Over | NotePosition::Across);
+    let text_x = if centered {
+        x + note_width / 2.0
+    } else if shape == NoteShape::Hexagon {
+        x + NOTE_HEXAGON_INSET + ELEMENT_PADDING
+    } else {
+        x + ELEMENT_PADDING
     };
-    let text_anchor_attr = if *position == NotePosition::Over { "middle" } else { "start" };
+    let text_anchor_attr = if centered { "middle" } else { "start" };
 
     for (i, line) in lines.iter().enumerate() {
         let text_y = y + ELEMENT_PADDING + (i as f64 + 0.8) * line_height;
@@ -3159,6 +4416,105 @@ fn render_note(
     state.current_y += note_y_advance(&state.config, lines.len());
 }
 
+/// Draw a note outline with its top-left corner at (x, y)
+fn render_note_box(
+    svg: &mut String,
+    theme: &Theme,
+    shape: NoteShape,
+    x: f64,
+    y: f64,
+    note_width: f64,
+    note_height: f64,
+    fill_style: &str,
+) {
+    match shape {
+        NoteShape::Folded => {
+            // Note background with dog-ear (folded corner) effect
+            // Path: start at top-left, go right (leaving space for fold), diagonal fold, down, left, up
+            let note_path = format!(
+                "M {x} {y} L {x2} {y} L {x3} {y2} L {x3} {y3} L {x} {y3} Z",
+                x = x,
+                y = y,
+                x2 = x + note_width - NOTE_FOLD_SIZE,
+                x3 = x + note_width,
+                y2 = y + NOTE_FOLD_SIZE,
+                y3 = y + note_height
+            );
+
+            writeln!(svg, r#"<path d="{path}" class="note"{fill_style}/>"#, path = note_path).unwrap();
+
+            // Draw the fold triangle (represents the folded corner)
+            // Triangle: from fold start, to diagonal corner, to bottom of fold
+            let fold_path = format!(
+                "M {x1} {y1} L {x2} {y2} L {x1} {y2} Z",
+                x1 = x + note_width - NOTE_FOLD_SIZE,
+                y1 = y,
+                x2 = x + note_width,
+                y2 = y + NOTE_FOLD_SIZE
+            );
+
+            writeln!(
+                svg,
+                r##"<path d="{path}" fill="none" stroke="{stroke}" stroke-width="1"/>"##,
+                path = fold_path,
+                stroke = theme.note_stroke
+            )
+            .unwrap();
+        }
+        NoteShape::Rectangle => {
+            writeln!(
+                svg,
+                r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="note"{fill_style}/>"#,
+                x = x,
+                y = y,
+                w = note_width,
+                h = note_height
+            )
+            .unwrap();
+        }
+        NoteShape::Hexagon => {
+            // Flat top and bottom, pointed left and right ends
+            let note_path = format!(
+                "M {x} {ym} L {x1} {y} L {x2} {y} L {x3} {ym} L {x2} {y3} L {x1} {y3} Z",
+                x = x,
+                y = y,
+                x1 = x + NOTE_HEXAGON_INSET,
+                x2 = x + note_width - NOTE_HEXAGON_INSET,
+                x3 = x + note_width,
+                ym = y + note_height / 2.0,
+                y3 = y + note_height
+            );
+
+            writeln!(svg, r#"<path d="{path}" class="note"{fill_style}/>"#, path = note_path).unwrap();
+        }
+    }
+}
+
+/// Draw a note attached to a message (`note on link`) with its top-left corner at
+/// (x, y), moved down if it would cover another message label
+fn render_link_note(svg: &mut String, state: &mut RenderState, text: &str, x: f64, y: f64) {
+    let lines: Vec<&str> = text.split("\\n").collect();
+    let line_height = note_line_height(&state.config);
+    let note_width = calculate_note_width(text, NoteShape::Folded, &state.config);
+    let note_height = ELEMENT_PADDING * 2.0 + lines.len() as f64 * line_height;
+    let x = x.max(state.config.padding);
+
+    let step = line_height * MESSAGE_LABEL_COLLISION_STEP_RATIO;
+    let y = y + state.reserve_message_label(x, x + note_width, y, y + note_height, step);
+
+    render_note_box(svg, &state.config.theme, NoteShape::Folded, x, y, note_width, note_height, "");
+    for (i, line) in lines.iter().enumerate() {
+        writeln!(
+            svg,
+            r#"<text x="{x}" y="{y}" class="note-text" text-anchor="start">{t}</text>"#,
+            x = x + ELEMENT_PADDING,
+            y = y + ELEMENT_PADDING + (i as f64 + 0.8) * line_height,
+            t = escape_xml(line)
+        )
+        .unwrap();
+    }
+}
+
 /// Render a state box (rounded rectangle)
 fn render_state(svg: &mut String, state: &mut RenderState, participants: &[String], text: &str) {
     let theme = &state.config.theme;
@@ -3449,6 +4805,87 @@ fn render_description(svg: &mut String, state: &mut RenderState, text: &str) {
     state.current_y += description_y_advance(&state.config, lines.len());
 }
 
+/// Render a `...` delay label; the dotted lifeline gap is drawn with the lifelines
+fn render_delay(svg: &mut String, state: &mut RenderState, text: &str) {
+    if !text.is_empty() {
+        let top = delay_gap_top(&state.config, state.current_y);
+        writeln!(
+            svg,
+            r#"<text x="{x}" y="{y}" class="delay-text" text-anchor="middle" dominant-baseline="central">{t}</text>"#,
+            x = (state.block_left() + state.block_right()) / 2.0,
+            y = top + DELAY_GAP_HEIGHT / 2.0,
+            t = escape_xml(text)
+        )
+        .unwrap();
+    }
+    state.current_y += delay_y_advance(&state.config);
+}
+
+/// Split a lifeline into solid and dotted (`...` delay) segments
+fn lifeline_segments(start: f64, end: f64, gaps: &[(f64, f64)]) -> Vec<(f64, f64, bool)> {
+    let mut gaps = gaps.to_vec();
+    gaps.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut segments = Vec::new();
+    let mut y = start;
+    for (top, bottom) in gaps {
+        let (top, bottom) = (top.max(y), bottom.min(end));
+        if top >= bottom {
+            continue;
+        }
+        if top > y {
+            segments.push((y, top, false));
+        }
+        segments.push((top, bottom, true));
+        y = bottom;
+    }
+    if y < end {
+        segments.push((y, end, false));
+    }
+    segments
+}
+
+/// Render a section divider: a double line across the diagram with a centered label box
+fn render_divider(svg: &mut String, state: &mut RenderState, text: &str) {
+    let x1 = state.config.padding.min(state.block_left());
+    let x2 = (state.diagram_width() - state.config.padding).max(state.block_right());
+    let box_height = divider_box_height(&state.config);
+    let top = state.current_y;
+    let center_y = top + box_height / 2.0;
+
+    for offset in [-DIVIDER_LINE_GAP / 2.0, DIVIDER_LINE_GAP / 2.0] {
+        writeln!(
+            svg,
+            r#"<line x1="{x1}" y1="{y}" x2="{x2}" y2="{y}" class="divider-line"/>"#,
+            y = center_y + offset
+        )
+        .unwrap();
+    }
+
+    if !text.is_empty() {
+        let width = estimate_message_width(text, state.config.font_size) + DIVIDER_TEXT_PADDING * 2.0;
+        let center_x = (x1 + x2) / 2.0;
+        writeln!(
+            svg,
+            r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="divider-box"/>"#,
+            x = center_x - width / 2.0,
+            y = top,
+            w = width,
+            h = box_height
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"<text x="{x}" y="{y}" class="divider-text" text-anchor="middle" dominant-baseline="central">{t}</text>"#,
+            x = center_x,
+            y = center_y,
+            t = escape_xml(text)
+        )
+        .unwrap();
+    }
+
+    state.current_y += divider_y_advance(&state.config);
+}
+
 fn render_block(
     svg: &mut String,
     state: &mut RenderState,
@@ -3462,19 +4899,30 @@ fn render_block(
         state.push_parallel();
         let start_y = state.current_y;
         let mut max_end_y = start_y;
-        for item in items {
+        // Labels on an `&` row only make room for each other
+        let outer_row_start = state.message_row_start;
+        let row_offsets = if *kind == BlockKind::Row {
+            state.message_row_start = Some(state.message_label_boxes.len());
+            row_label_offsets(state, items)
+        } else {
+            Vec::new()
+        };
+        for (index, item) in items.iter().enumerate() {
             state.current_y = start_y;
+            state.message_row_offset = row_offsets.get(index).copied();
             render_items(svg, state, std::slice::from_ref(item), depth);
             if state.current_y > max_end_y {
                 max_end_y = state.current_y;
             }
         }
+        state.message_row_start = outer_row_start;
+        state.message_row_offset = None;
         let gap = if parallel_needs_gap(items) {
             state.config.row_height
         } else {
             0.0
         };
-        state.current_y = max_end_y + gap;
+        state.current_y = max_end_y + gap + message_row_label_room(state, kind, items);
         state.pop_parallel();
         return;
     }
@@ -3530,18 +4978,24 @@ fn render_block(
 
 fn render_activations(svg: &mut String, state: &mut RenderState, footer_y: f64) {
     for (participant, activations) in &state.activations {
-        let x = state.get_x(participant);
-        let box_x = x - state.config.activation_width / 2.0;
-
-        for (start_y, end_y) in activations {
+        // Outer bars come first, so nested bars are drawn on top
+        for act in activations {
+            let box_x = state.activation_center_x(participant, act.depth) - state.config.activation_width / 2.0;
+            let start_y = act.start_y;
             // If no end_y, extend to footer
-            let end = end_y.unwrap_or(footer_y);
+            let end = act.end_y.unwrap_or(footer_y);
             let height = end - start_y;
 
             if height > 0.0 {
+                let fill_style = style_attr(
+                    &act.color
+                        .iter()
+                        .map(|c| format!("fill: {}", escape_xml(&css_color(c))))
+                        .collect::<Vec<_>>(),
+                );
                 writeln!(
                     svg,
-                    r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="activation"/>"#,
+                    r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" class="activation"{fill_style}/>"#,
                     x = box_x,
                     y = start_y,
                     w = state.config.activation_width,
@@ -3592,6 +5046,13 @@ mod tests {
     use super::*;
     use crate::parser::parse;
 
+    /// Content height of a diagram, as computed before drawing it
+    fn content_height(input: &str, config: &Config) -> f64 {
+        let diagram = parse(input).unwrap();
+        let state = RenderState::new(config.clone(), diagram.participants(), &diagram.items, false, diagram.options.footer);
+        calculate_height(&diagram.items, &state, 0)
+    }
+
     #[test]
     fn test_render_simple() {
         let diagram = parse("Alice->Bob: Hello").unwrap();
@@ -3608,4 +5069,381 @@ mod tests {
         let svg = render(&diagram);
         assert!(svg.contains("Thinking"));
     }
+
+    #[test]
+    fn test_render_uml_fragments() {
+        let diagram = parse("Alice->Bob: a\nBob->Carol: b\nbreak timeout\nAlice->Bob: c\nend\nignore {b, c}\nAlice->Bob: d\nend").unwrap();
+        let svg = render(&diagram);
+        assert!(svg.contains(">ignore {b, c}</text>"));
+
+        // The break frame spans all lifelines even though only Alice and Bob are involved
+        let state = RenderState::new(Config::default(), diagram.participants(), &diagram.items, false, diagram.options.footer);
+        let frame = svg
+            .lines()
+            .filter(|line| line.contains(r#"class="block""#))
+            .find(|line| line.contains(&format!(r#"x="{}""#, state.block_left())))
+            .expect("break frame at the left edge");
+        assert!(frame.contains(&format!(r#"width="{}""#, state.block_right() - state.block_left())));
+    }
+
+    #[test]
+    fn test_render_group() {
+        let diagram = parse("group Retry <policy> [max 3]\nAlice->Bob: Hello\nend").unwrap();
+        let svg = render(&diagram);
+        assert!(svg.contains(">Retry &lt;policy&gt;</text>"));
+        assert!(svg.contains(">[max 3]</text>"));
+        assert!(!svg.contains(">group</text>"));
+    }
+
+    #[test]
+    fn test_render_participant_boxes() {
+        let input = "participant Client\nbox \"A rather long backend title\" #lightblue\nparticipant API\nend box\nparticipant DB\nClient->API: GET\nAPI->DB: query";
+        let diagram = parse(input).unwrap();
+        let svg = render(&diagram);
+        assert!(svg.contains(r#"class="participant-box" style="fill: lightblue""#));
+        assert!(svg.contains(">A rather long backend title</text>"));
+
+        let state = RenderState::new(Config::default(), diagram.participants(), &diagram.items, false, diagram.options.footer);
+        // Headers move down to make room for the box title
+        assert_eq!(state.header_top(), Config::default().padding + BOX_PADDING + BOX_TITLE_HEIGHT);
+        // The gaps around a single-member box leave room for its title and padding
+        let title_width = box_title_width("A rather long backend title", &state.config);
+        let left_edge = state.get_x("Client") + state.get_participant_width("Client") / 2.0;
+        let right_edge = state.get_x("DB") - state.get_participant_width("DB") / 2.0;
+        assert!(right_edge - left_edge >= title_width);
+    }
+
+    #[test]
+    fn test_render_participant_kinds() {
+        let input = "database DB\nqueue Jobs\ncollections Items\nboundary UI\ncontrol Ctl\nentity Order\nDB->Jobs: a";
+        let diagram = parse(input).unwrap();
+        let svg = render(&diagram);
+        // Header and footer for each kind
+        assert_eq!(svg.matches("<ellipse").count(), 4); // database and queue caps
+        assert_eq!(svg.matches(r#"<circle"#).count(), 6); // ICONIX icons
+        assert_eq!(svg.matches("<polyline").count(), 2); // control arrowhead
+        assert!(svg.contains(">Order</tspan>"));
+
+        // Queue and collections outlines are wider than their names need
+        let plain = calculate_participant_width("Jobs", ParticipantKind::Participant, 0.0);
+        assert_eq!(calculate_participant_width("Jobs", ParticipantKind::Queue, 0.0), plain + QUEUE_CAP_WIDTH * 2.0);
+        assert_eq!(calculate_participant_width("Jobs", ParticipantKind::Collections, 0.0), plain + COLLECTIONS_OFFSET);
+    }
+
+    #[test]
+    fn test_render_participant_style() {
+        let diagram = parse("participant A [fill=#ffe0e0, stroke=#c00, text=#900, lifeline=#lightblue]\nA->B: hi").unwrap();
+        let svg = render(&diagram);
+        // Header and footer
+        assert_eq!(svg.matches(r#"class="participant" style="fill: #ffe0e0; stroke: #c00""#).count(), 2);
+        assert_eq!(svg.matches(r#"class="participant-text" style="fill: #900">A<"#).count(), 2);
+        assert_eq!(svg.matches(r#"class="lifeline" style="stroke: lightblue""#).count(), 1);
+        // B keeps the theme styling
+        assert_eq!(svg.matches(r#"class="participant"/>"#).count(), 2);
+    }
+
+    #[test]
+    fn test_render_message_style() {
+        let diagram = parse("A-[#red]>B: fail\nB-->>A: retry [color=#c00, bold, text=#900]\nA->B: ok").unwrap();
+        let svg = render(&diagram);
+        // Overrides go on the styled message's own elements; the theme classes stay as they are
+        assert!(svg.contains(r#"class="message" style="stroke: red"/>"#));
+        assert!(svg.contains(r#"class="arrowhead" style="fill: red"/>"#));
+        assert!(svg.contains(r#"class="message-dashed" style="stroke: #c00; stroke-width: 3"/>"#));
+        assert!(svg.contains(r#"class="arrowhead-open" style="stroke: #c00"/>"#));
+        assert!(svg.contains(r#"class="message-text" style="fill: #900" text-anchor="middle">retry<"#));
+        assert!(!svg.contains("var(--"));
+        // Unstyled messages keep the theme classes
+        assert!(svg.contains(r#"class="message-text" text-anchor="middle">ok<"#));
+    }
+
+    #[test]
+    fn test_render_bidirectional_arrows() {
+        let diagram = parse("A<->B: sync\nB<-->A: reply\nA<->A: self\n[<->A: gate").unwrap();
+        let svg = render(&diagram);
+        assert_eq!(svg.matches(r#"class="arrowhead""#).count(), 8);
+
+        // The line is shortened at both ends so the heads don't overlap it
+        let diagram = parse("A<->B: sync").unwrap();
+        let svg = render(&diagram);
+        let x_attrs = |class: &str| -> Vec<f64> {
+            let line = svg.lines().find(|l| l.trim_start().starts_with("<line") && l.contains(&format!(r#"class="{}""#, class))).unwrap();
+            ["x1", "x2"]
+                .iter()
+                .map(|a| {
+                    let start = line.find(&format!(r#" {}=""#, a)).unwrap() + a.len() + 3;
+                    line[start..start + line[start..].find('"').unwrap()].parse().unwrap()
+                })
+                .collect()
+        };
+        let message = x_attrs("message");
+        let lifeline_a = x_attrs("lifeline")[0];
+        assert!((message[0] - lifeline_a - ARROWHEAD_SIZE).abs() < 0.01);
+        assert!(message[1] > message[0]);
+    }
+
+    #[test]
+    fn test_render_arrowhead_shapes() {
+        let diagram = parse("A ->x B: lost\nA o-> B: found\nA-\\B: upper\nB-/A: lower").unwrap();
+        let svg = render(&diagram);
+        assert_eq!(svg.matches(r#"class="arrowhead-cross""#).count(), 1);
+        assert_eq!(svg.matches(r#"<circle"#).count(), 1);
+        assert_eq!(svg.matches(r#"<polygon"#).count(), 3);
+
+        // Half arrowheads keep their barb above the line in either direction
+        let barb_above = |points: &str| {
+            let ys: Vec<f64> = points
+                .split(' ')
+                .map(|p| p.split(',').nth(1).unwrap().parse().unwrap())
+                .collect();
+            ys[0] < ys[1]
+        };
+        assert!(barb_above(&half_arrowhead_points(100.0, 50.0, 0.0, true)));
+        assert!(barb_above(&half_arrowhead_points(100.0, 50.0, std::f64::consts::PI, true)));
+        assert!(!barb_above(&half_arrowhead_points(100.0, 50.0, std::f64::consts::PI, false)));
+    }
+
+    #[test]
+    fn test_autonumbering() {
+        let labels = |input: &str| -> Vec<String> {
+            let diagram = parse(input).unwrap();
+            let mut numbering = Autonumbering::default();
+            let mut labels = Vec::new();
+            for item in &diagram.items {
+                match item {
+                    Item::Autonumber { command, .. } => numbering.apply(command),
+                    Item::Message { .. } => labels.push(numbering.next_label().unwrap_or_default()),
+                    _ => {}
+                }
+            }
+            labels
+        };
+        assert_eq!(labels("A->B\nautonumber\nA->B\nA->B"), vec!["", "1", "2"]);
+        assert_eq!(labels("autonumber 10 10 \"[000]\"\nA->B\nA->B"), vec!["[010]", "[020]"]);
+        assert_eq!(
+            labels("autonumber 1.1\nA->B\nA->B\nautonumber inc A\nA->B"),
+            vec!["1.1", "1.2", "2.1"]
+        );
+        assert_eq!(
+            labels("autonumber 5\nA->B\nautonumber off\nA->B\nautonumber resume\nA->B"),
+            vec!["5", "", "6"]
+        );
+        assert_eq!(format_autonumber(&[2, 3], Some("Step ##")), "Step 2.3");
+    }
+
+    #[test]
+    fn test_render_autonumber_badge() {
+        let diagram = parse("autonumber\nA->B: hello").unwrap();
+        let svg = render(&diagram);
+        assert!(svg.contains(r#"class="autonumber-badge""#));
+        assert!(svg.contains(r#"class="autonumber-text" text-anchor="middle" dominant-baseline="central">1<"#));
+        // The number is not part of the label
+        assert!(svg.contains(r#"text-anchor="middle">hello<"#));
+
+        // Room for the badge is included in the participant gap
+        let gap = |input: &str| {
+            let diagram = parse(input).unwrap();
+            let state = RenderState::new(Config::default(), diagram.participants(), &diagram.items, false, diagram.options.footer);
+            state.get_x("B") - state.get_x("A")
+        };
+        let long = "A->B: a fairly long message label that sets the gap";
+        assert!(gap(&format!("autonumber\n{}", long)) > gap(long));
+    }
+
+    #[test]
+    fn test_render_divider() {
+        let input = "A->B: a\nB->C: b";
+        let with_divider = "A->B: a\n== Phase <2> ==\nB->C: b";
+        let svg = render(&parse(with_divider).unwrap());
+        assert_eq!(svg.matches(r#"class="divider-line""#).count(), 2);
+        assert!(svg.contains(r#"class="divider-text" text-anchor="middle" dominant-baseline="central">Phase &lt;2&gt;<"#));
+
+        // The divider adds height and spans every lifeline
+        let diagram = parse(with_divider).unwrap();
+        let config = Config::default();
+        assert!(
+            content_height(with_divider, &config) > content_height(input, &config)
+        );
+        let state = RenderState::new(config, diagram.participants(), &diagram.items, false, diagram.options.footer);
+        let line = svg.lines().find(|l| l.contains(r#"class="divider-line""#)).unwrap();
+        let x1: f64 = line.split('"').nth(1).unwrap().parse().unwrap();
+        assert!(x1 <= state.block_left());
+
+        // A block containing a divider covers all participants
+        let diagram = parse("A->B: a\nloop retry\nA->B: again\n== Phase 2 ==\nend\nB->C: b").unwrap();
+        let mut state = RenderState::new(Config::default(), diagram.participants(), &diagram.items, false, diagram.options.footer);
+        let mut active = 0;
+        collect_block_backgrounds(&mut state, &diagram.items, 0, &mut active, None);
+        let background = &state.block_backgrounds[0];
+        assert!(background.x + background.width > state.get_x("C"));
+    }
+
+    #[test]
+    fn test_render_spacer_and_delay() {
+        let config = Config::default();
+        let height = |input: &str| content_height(input, &config);
+        let base = height("A->B: a\nA->B: b");
+        assert_eq!(height("A->B: a\n|||\nA->B: b"), base + config.row_height);
+        assert_eq!(height("A->B: a\n||45||\nA->B: b"), base + 45.0);
+        assert_eq!(height("A->B: a\n... later ...\nA->B: b"), base + delay_y_advance(&config));
+
+        let svg = render(&parse("A->B: a\n... 5 min later ...\nA->B: b").unwrap());
+        // One dotted segment per lifeline, with solid lifeline above and below
+        assert_eq!(svg.matches(r#"class="lifeline-delay""#).count(), 2);
+        assert_eq!(svg.matches(r#"class="lifeline""#).count(), 4);
+        assert!(svg.contains(r#"class="delay-text" text-anchor="middle" dominant-baseline="central">5 min later<"#));
+
+        assert_eq!(
+            lifeline_segments(0.0, 100.0, &[(60.0, 70.0), (20.0, 30.0)]),
+            vec![
+                (0.0, 20.0, false),
+                (20.0, 30.0, true),
+                (30.0, 60.0, false),
+                (60.0, 70.0, true),
+                (70.0, 100.0, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_created_participant() {
+        let config = Config::default();
+        let height = |input: &str| content_height(input, &config);
+        assert_eq!(height("A->B: a\ncreate C"), height("A->B: a") + create_y_advance(&config));
+
+        let svg = render(&parse("A->B: a\nA->*C: new\ncreate D\nB->D: b").unwrap());
+        let rect_ys: Vec<&str> = svg
+            .lines()
+            .filter(|l| l.starts_with("<rect") && l.contains(r#"class="participant""#))
+            .map(|l| l.split(r#" y=""#).nth(1).unwrap().split('"').next().unwrap())
+            .collect();
+        // Headers of C and D sit on their creation rows, then four footers
+        assert_eq!(rect_ys[..4], ["10.5", "10.5", "97.5", "161.5"]);
+        assert_eq!(rect_ys.len(), 8);
+
+        // C's lifeline starts below its header, and the arrow ends on the header edge
+        assert!(svg.contains(r#"<line x1="286" y1="143.5" x2="286""#));
+        assert!(svg.contains(r#"<line x1="67" y1="120.5" x2="230" y2="120.5" class="message"/>"#));
+    }
+
+    #[test]
+    fn test_render_nested_activations() {
+        let svg = render(&parse("Alice->+Bob: call\nBob->+Bob: recurse\nBob->Alice: back\nBob->Carol: on\ndeactivate Bob\ndeactivate Bob").unwrap());
+        let bars: Vec<&str> = svg.lines().filter(|l| l.contains(r#"class="activation""#)).collect();
+        // The re-entrant bar is shifted right by half a bar width
+        assert_eq!(bars.len(), 2);
+        assert!(bars[0].starts_with(r#"<rect x="180" y="88.5""#));
+        assert!(bars[1].starts_with(r#"<rect x="184" y="120.5""#));
+
+        // Loops and arrows snap to the innermost bar's edges
+        assert!(svg.contains(r#"<path d="M 192 120.5 L 232 120.5"#));
+        assert!(svg.contains(r#"<line x1="184" y1="183.5" x2="77" y2="183.5" class="message"/>"#));
+        assert!(svg.contains(r#"<line x1="192" y1="215.5""#));
+    }
+
+    #[test]
+    fn test_render_activation_colors() {
+        let svg = render(&parse("Alice->+[#ffcccc]Bob: lock\nactivate Alice #lightblue\nBob->+Carol: plain").unwrap());
+        let bars: Vec<&str> = svg.lines().filter(|l| l.contains(r#"class="activation""#)).collect();
+        assert_eq!(bars.len(), 3);
+        assert_eq!(bars.iter().filter(|l| l.ends_with(r#"class="activation" style="fill: #ffcccc"/>"#)).count(), 1);
+        assert_eq!(bars.iter().filter(|l| l.ends_with(r#"class="activation" style="fill: lightblue"/>"#)).count(), 1);
+        assert_eq!(bars.iter().filter(|l| l.ends_with(r#"class="activation"/>"#)).count(), 1);
+    }
+
+    #[test]
+    fn test_render_note_shapes() {
+        let svg = render(&parse("A->B: hi\nrnote over A #yellow: boxed\nhnote right of B: step\nnote over A: folded").unwrap());
+        assert!(svg.contains(r#"class="note" style="fill: yellow"/>"#));
+        assert_eq!(svg.lines().filter(|l| l.starts_with("<rect") && l.contains(r#"class="note""#)).count(), 1);
+        let hexagon = svg.lines().find(|l| l.contains("class=\"note\"") && l.matches(" L ").count() == 5);
+        assert!(hexagon.is_some(), "expected a hexagon note path");
+        // Only the folded note draws a corner triangle
+        assert_eq!(svg.matches(r#"fill="none" stroke="#).count(), 1);
+    }
+
+    #[test]
+    fn test_render_note_across() {
+        let svg = render(&parse("A->B: hi\nB->C: there\nnote across: A much longer note than the space between the three lifelines").unwrap());
+        let config = Config::default();
+        let state = RenderState::new(config, parse("A->B: hi\nB->C: there").unwrap().participants(), &[], false, FooterStyle::Box);
+        let (a, c) = (state.get_x("A"), state.get_x("C"));
+        let path = svg.lines().find(|l| l.contains(r#"class="note""#)).unwrap();
+        assert!(path.starts_with(&format!("<path d=\"M {} ", a - NOTE_MARGIN)), "{}", path);
+
+        // The note is wider than A..C, so the diagram grows to fit it
+        let note_width = calculate_note_width(
+            "A much longer note than the space between the three lifelines",
+            NoteShape::Folded,
+            &Config::default(),
+        );
+        assert!(note_width > c - a + NOTE_MARGIN * 2.0);
+        let width: f64 = svg
+            .split("width=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(width >= a - NOTE_MARGIN + note_width, "{} < {}", width, a - NOTE_MARGIN + note_width);
+    }
+
+    #[test]
+    fn test_render_link_note() {
+        let config = Config::default();
+        let height = |input: &str| content_height(input, &config);
+        let plain = "A->B: call\nB-->A: done";
+        let noted = "A->B: call\nnote on link: payload schema v2\nB-->A: done";
+        let extra = link_note_y_extra(&config, Some("payload schema v2"), false, 1, 0.0);
+        assert!(extra > 0.0);
+        assert_eq!(height(noted), height(plain) + extra);
+
+        let svg = render(&parse(noted).unwrap());
+        let arrows: Vec<f64> = svg
+            .lines()
+            .filter(|l| l.contains("<line") && l.contains(r#"class="message"#))
+            .map(|l| l.split("y1=\"").nth(1).unwrap().split('"').next().unwrap().parse().unwrap())
+            .collect();
+        let note = svg.lines().find(|l| l.contains(r#"class="note""#)).unwrap();
+        let note_top: f64 = note.split(' ').nth(3).unwrap().parse().unwrap();
+        // The note hangs just below its arrow and clears the next one
+        assert_eq!(note_top, arrows[0] + LINK_NOTE_GAP);
+        assert!(note_top + 33.0 < arrows[1] - config.font_size);
+
+        // Self-message notes sit to the right of the loop and widen the diagram
+        let width = |input: &str| {
+            let diagram = parse(input).unwrap();
+            RenderState::new(config.clone(), diagram.participants(), &diagram.items, false, FooterStyle::Box).total_width
+        };
+        assert!(width("A->B: x\nB->B: retry\nnote on link: backoff with jitter") > width("A->B: x\nB->B: retry"));
+    }
+
+    #[test]
+    fn test_render_message_row() {
+        let config = Config::default();
+        let input = "participant A\nparticipant B\nparticipant C\nA->B: hello there & A->C: world wide\nB->A: next";
+        let svg = render(&parse(input).unwrap());
+        let arrows: Vec<f64> = svg
+            .lines()
+            .filter(|l| l.contains("<line") && l.contains(r#"class="message"#))
+            .map(|l| l.split("y1=\"").nth(1).unwrap().split('"').next().unwrap().parse().unwrap())
+            .collect();
+        // Both row messages share one y, the next message follows on its own row
+        assert_eq!(arrows.len(), 3);
+        assert_eq!(arrows[0], arrows[1]);
+        assert!(arrows[2] > arrows[0]);
+
+        // The second label collides with the first and moves below its arrow
+        let label_y = |label: &str| -> f64 {
+            let line = svg.lines().find(|l| l.ends_with(&format!(">{}</text>", label))).unwrap();
+            line.split("y=\"").nth(1).unwrap().split('"').next().unwrap().parse().unwrap()
+        };
+        assert!(label_y("hello there") < arrows[0]);
+        assert_eq!(label_y("world wide"), label_y("hello there") + row_label_drop(&config, 1));
+
+        // The row only leaves room below it for labels that actually moved
+        let height = |input: &str| content_height(input, &config);
+        assert_eq!(height(input), height("A->B: hello there\nB->A: next") + row_label_drop(&config, 1));
+        assert_eq!(height("A->B: x & A->C: y\n& B->C: z"), height("A->B: x"));
+    }
 }
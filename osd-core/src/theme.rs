@@ -81,6 +81,10 @@ pub struct Theme {
     pub ref_text_color: String,
     /// Description text color
     pub description_text_color: String,
+    /// Autonumber badge fill color
+    pub autonumber_fill: String,
+    /// Autonumber badge text color
+    pub autonumber_text_color: String,
 }
 
 impl Default for Theme {
@@ -121,6 +125,8 @@ impl Theme {
             ref_stroke: "#ff9800".to_string(),
             ref_text_color: "#000".to_string(),
             description_text_color: "#666".to_string(),
+            autonumber_fill: "#333".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -155,6 +161,8 @@ impl Theme {
             ref_stroke: "#4a90d9".to_string(),
             ref_text_color: "#000".to_string(),
             description_text_color: "#666".to_string(),
+            autonumber_fill: "#333".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -189,6 +197,8 @@ impl Theme {
             ref_stroke: "#2d8659".to_string(),
             ref_text_color: "#000".to_string(),
             description_text_color: "#666".to_string(),
+            autonumber_fill: "#2d8659".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -223,6 +233,8 @@ impl Theme {
             ref_stroke: "#c2185b".to_string(),
             ref_text_color: "#000".to_string(),
             description_text_color: "#666".to_string(),
+            autonumber_fill: "#c2185b".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -257,6 +269,8 @@ impl Theme {
             ref_stroke: "#333".to_string(),
             ref_text_color: "#000".to_string(),
             description_text_color: "#666".to_string(),
+            autonumber_fill: "#333".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -291,6 +305,8 @@ impl Theme {
             ref_stroke: "#8d6e63".to_string(),
             ref_text_color: "#3e2723".to_string(),
             description_text_color: "#5d4037".to_string(),
+            autonumber_fill: "#5d4037".to_string(),
+            autonumber_text_color: "#faf8f5".to_string(),
         }
     }
 
@@ -325,6 +341,8 @@ impl Theme {
             ref_stroke: "#000".to_string(),
             ref_text_color: "#000".to_string(),
             description_text_color: "#333".to_string(),
+            autonumber_fill: "#000".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -359,6 +377,8 @@ impl Theme {
             ref_stroke: "#56ab91".to_string(),
             ref_text_color: "#2d5a4a".to_string(),
             description_text_color: "#56ab91".to_string(),
+            autonumber_fill: "#56ab91".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -393,6 +413,8 @@ impl Theme {
             ref_stroke: "#1976d2".to_string(),
             ref_text_color: "#1976d2".to_string(),
             description_text_color: "#1976d2".to_string(),
+            autonumber_fill: "#1976d2".to_string(),
+            autonumber_text_color: "#fff".to_string(),
         }
     }
 
@@ -427,6 +449,8 @@ impl Theme {
             ref_stroke: "#ef6c00".to_string(),
             ref_text_color: "#000".to_string(),
             description_text_color: "#ef6c00".to_string(),
+            autonumber_fill: "#ef6c00".to_string(),
+            autonumber_text_color: "#fffbf0".to_string(),
         }
     }
 
@@ -461,6 +485,8 @@ impl Theme {
             ref_stroke: "#757575".to_string(),
             ref_text_color: "#212121".to_string(),
             description_text_color: "#757575".to_string(),
+            autonumber_fill: "#424242".to_string(),
+            autonumber_text_color: "#fafafa".to_string(),
         }
     }
 
@@ -495,6 +521,8 @@ impl Theme {
             ref_stroke: "#22c55e".to_string(),
             ref_text_color: "#22c55e".to_string(),
             description_text_color: "#22c55e".to_string(),
+            autonumber_fill: "#22c55e".to_string(),
+            autonumber_text_color: "#08090a".to_string(),
         }
     }
 
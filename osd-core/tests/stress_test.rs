@@ -1,4 +1,4 @@
-use osd_core::{parse, render, Diagram, Item};
+use osd_core::{parse, render, Item};
 use std::fs;
 
 #[test]
@@ -157,7 +157,7 @@ end ref
     let svg = render(&diagram);
 
     // Write to file for comparison
-    let output_path = "/Users/kondomasaki/Documents/osd/opensequencediagrams-web/.claude/stress_test/OSD_Ultimate_Stress_Test_NEW.svg";
+    let output_path = std::env::temp_dir().join("OSD_Ultimate_Stress_Test_NEW.svg");
     fs::write(output_path, &svg).expect("Failed to write SVG");
 
     // Print first few lines to verify
@@ -1,6 +1,6 @@
 //! WebAssembly bindings for OpenSequenceDiagrams
 
-use osd_core::{Config, Theme};
+use osd_core::{Config, MemoryResolver, Theme};
 use wasm_bindgen::prelude::*;
 
 /// Render a sequence diagram to SVG
@@ -37,6 +37,28 @@ pub fn render_with_theme(input: &str, theme_name: &str) -> Result<String, String
     }
 }
 
+/// Render a sequence diagram to SVG, resolving `!include` directives
+///
+/// # Arguments
+/// * `input` - The sequence diagram source code
+/// * `paths` - Names of the files that can be included
+/// * `sources` - Source code of each file, in the same order as `paths`
+///
+/// # Returns
+/// The rendered SVG as a string, or an error message
+#[wasm_bindgen]
+pub fn render_with_includes(input: &str, paths: Vec<String>, sources: Vec<String>) -> Result<String, String> {
+    let mut resolver = MemoryResolver::new();
+    for (path, source) in paths.into_iter().zip(sources) {
+        resolver.insert(path, source);
+    }
+
+    match osd_core::parse_with_resolver(input, &resolver) {
+        Ok(diagram) => Ok(osd_core::render(&diagram)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
 /// Get a list of available theme names
 #[wasm_bindgen]
 pub fn available_themes() -> Vec<String> {
@@ -73,10 +95,7 @@ pub fn parse_to_json(input: &str) -> Result<String, String> {
                 json.push_str(&format!(
                     r#"{{"name":"{}","kind":"{}"}}"#,
                     escape_json(&p.name),
-                    match p.kind {
-                        osd_core::ParticipantKind::Participant => "participant",
-                        osd_core::ParticipantKind::Actor => "actor",
-                    }
+                    p.kind.as_str()
                 ));
             }
             json.push_str("],");
@@ -90,6 +109,79 @@ pub fn parse_to_json(input: &str) -> Result<String, String> {
     }
 }
 
+/// Parse a sequence diagram and return every problem found as JSON
+///
+/// # Arguments
+/// * `input` - The sequence diagram source code
+///
+/// # Returns
+/// A JSON array of diagnostics with 1-based line/column ranges
+#[wasm_bindgen]
+pub fn diagnostics(input: &str) -> String {
+    let output = osd_core::parse_with_diagnostics(input);
+    diagnostics_to_json(&output.diagnostics)
+}
+
+/// Run the semantic lint pass and return its warnings as JSON
+///
+/// # Arguments
+/// * `input` - The sequence diagram source code
+/// * `disabled` - Comma-separated rule IDs to skip (e.g. "unused-participant")
+///
+/// # Returns
+/// A JSON array of diagnostics, each with a `code` naming its rule, or an error
+/// message naming a rule ID that doesn't exist
+#[wasm_bindgen]
+pub fn lint(input: &str, disabled: &str) -> Result<String, String> {
+    let output = osd_core::parse_with_diagnostics(input);
+    let mut config = osd_core::lint::LintConfig::default();
+    for id in disabled.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        match osd_core::lint::Rule::from_id(id) {
+            Some(rule) => config = config.disable(rule),
+            None => return Err(format!("unknown lint rule `{}`", id)),
+        }
+    }
+    Ok(diagnostics_to_json(&osd_core::lint::lint_with_config(&output.diagram, &config)))
+}
+
+fn diagnostics_to_json(diagnostics: &[osd_core::Diagnostic]) -> String {
+    let mut json = String::from("[");
+    for (i, d) in diagnostics.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(&format!(
+            r#"{{"severity":"{}","line":{},"column":{},"endLine":{},"endColumn":{},"message":"{}""#,
+            d.severity.as_str(),
+            d.span.line,
+            d.span.column,
+            d.span.end_line,
+            d.span.end_column,
+            escape_json(&d.message)
+        ));
+        if let Some(code) = d.code {
+            json.push_str(&format!(r#","code":"{}""#, code));
+        }
+        if let Some(related) = &d.related {
+            json.push_str(&format!(
+                r#","relatedLine":{},"relatedColumn":{},"relatedMessage":"{}""#,
+                related.span.line,
+                related.span.column,
+                escape_json(&related.message)
+            ));
+        }
+        if let Some(suggestion) = &d.suggestion {
+            json.push_str(&format!(r#","suggestion":"{}""#, escape_json(&suggestion.message)));
+            if let Some(replacement) = &suggestion.replacement {
+                json.push_str(&format!(r#","replacement":"{}""#, escape_json(replacement)));
+            }
+        }
+        json.push('}');
+    }
+    json.push(']');
+    json
+}
+
 /// Get version information
 #[wasm_bindgen]
 pub fn version() -> String {
@@ -116,6 +208,17 @@ mod tests {
         assert!(svg.contains("<svg"));
     }
 
+    #[test]
+    fn test_render_with_includes() {
+        let paths = vec!["greeting.osd".to_string()];
+        let sources = vec!["Alice->Bob: Hello".to_string()];
+        let svg = render_with_includes("!include greeting.osd", paths.clone(), sources).unwrap();
+        assert!(svg.contains("Hello"));
+
+        let error = render_with_includes("!include other.osd", paths, vec![]).unwrap_err();
+        assert!(error.contains("cannot include `other.osd`"));
+    }
+
     #[test]
     fn test_parse_to_json() {
         let result = parse_to_json("Alice->Bob: Hello");
@@ -123,4 +226,24 @@ mod tests {
         let json = result.unwrap();
         assert!(json.contains("Alice"));
     }
+
+    #[test]
+    fn test_diagnostics() {
+        let json = diagnostics("Alice->Bob: Hello\nactivate");
+        assert!(json.starts_with(r#"[{"severity":"error","line":2,"column":1"#));
+        assert_eq!(diagnostics("Alice->Bob: Hello"), "[]");
+        let json = diagnostics("box A\nbox B\nend box");
+        assert!(json.contains(r#""relatedLine":1,"relatedColumn":1,"relatedMessage":"outer box opened""#));
+    }
+
+    #[test]
+    fn test_lint() {
+        let json = lint("participant Carol\nAlice->Bob: Hello", "").unwrap();
+        assert!(json.contains(r#""code":"unused-participant""#));
+        assert_eq!(lint("participant Carol\nAlice->Bob: Hello", "unused-participant").unwrap(), "[]");
+        assert_eq!(
+            lint("Alice->Bob: Hello", "unused-participant, unused-participants").unwrap_err(),
+            "unknown lint rule `unused-participants`"
+        );
+    }
 }